@@ -0,0 +1,151 @@
+use embedded_hal::digital::OutputPin;
+use crate::clock::{Duration, Instant};
+use crate::state::State;
+
+const DEFAULT_MIN_DWELL: Duration = Duration::from_secs(3);
+
+// Raises the power antenna while the tuner is the active source and the unit is powered.
+// Toggles closer together than `min_dwell` are held back until it has elapsed, so the
+// motor always finishes a full travel before reversing.
+pub struct AntennaController<O>
+where
+    O: OutputPin
+{
+    relay_pin: O,
+    min_dwell: Duration,
+    last_toggle: Option<Instant>,
+    up: Option<bool>,
+}
+
+impl<O> AntennaController<O>
+where
+    O: OutputPin
+{
+    pub fn new(relay_pin: O) -> Self {
+        AntennaController {
+            relay_pin,
+            min_dwell: DEFAULT_MIN_DWELL,
+            last_toggle: None,
+            up: None,
+        }
+    }
+
+    pub fn with_min_dwell(mut self, min_dwell: Duration) -> Self {
+        self.min_dwell = min_dwell;
+        self
+    }
+
+    pub fn is_up(&self) -> bool {
+        self.up.unwrap_or(false)
+    }
+
+    pub fn update(&mut self, state: &State, now: Instant) {
+        let want_up = state.accessory_power() && state.tuner_active();
+
+        let dwell_elapsed = self.last_toggle
+            .is_none_or(|last| now.duration_since(last) >= self.min_dwell);
+
+        if self.up != Some(want_up) && dwell_elapsed {
+            let result = if want_up { self.relay_pin.set_high() } else { self.relay_pin.set_low() };
+            if result.is_ok() {
+                self.up = Some(want_up);
+                self.last_toggle = Some(now);
+            }
+        }
+
+        state.set_antenna_up(self.is_up());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::{Clock, MockClock};
+    use crate::mock::MockPin;
+    use crate::state::AudioSource;
+
+    fn setup() -> (AntennaController<MockPin>, MockPin, MockClock, State) {
+        let relay = MockPin::new(false);
+        let controller = AntennaController::new(relay.clone())
+            .with_min_dwell(Duration::from_secs(2));
+        (controller, relay, MockClock::new(), State::new())
+    }
+
+    #[test]
+    fn raises_for_tuner_when_powered() {
+        let (mut antenna, relay, clock, state) = setup();
+        antenna.update(&state, clock.now());
+
+        assert!(relay.get());
+        assert!(state.antenna_up());
+    }
+
+    #[test]
+    fn stays_down_for_other_sources_or_without_power() {
+        let (mut antenna, relay, clock, state) = setup();
+        state.set_audio_source(AudioSource::BLUETOOTH);
+        antenna.update(&state, clock.now());
+        assert!(!relay.get());
+
+        let (mut antenna, relay, clock, state) = setup();
+        state.set_accessory_power(false);
+        antenna.update(&state, clock.now());
+        assert!(!relay.get());
+        assert!(!state.antenna_up());
+    }
+
+    #[test]
+    fn lowers_on_source_change_after_dwell() {
+        let (mut antenna, relay, clock, state) = setup();
+        antenna.update(&state, clock.now());
+        assert!(relay.get());
+
+        clock.advance(Duration::from_millis(500));
+        state.set_audio_source(AudioSource::AUX);
+        antenna.update(&state, clock.now());
+        assert!(relay.get());
+        assert!(state.antenna_up());
+
+        clock.advance(Duration::from_millis(1500));
+        antenna.update(&state, clock.now());
+        assert!(!relay.get());
+        assert!(!state.antenna_up());
+    }
+
+    #[test]
+    fn lowers_on_shutdown() {
+        let (mut antenna, relay, clock, state) = setup();
+        antenna.update(&state, clock.now());
+
+        clock.advance(Duration::from_secs(10));
+        state.set_accessory_power(false);
+        antenna.update(&state, clock.now());
+        assert!(!relay.get());
+    }
+
+    #[test]
+    fn rapid_source_flapping_settles_on_final_source() {
+        let (mut antenna, relay, clock, state) = setup();
+        antenna.update(&state, clock.now());
+
+        let mut toggles = 0;
+        let mut last = relay.get();
+        for i in 0..40 {
+            state.set_audio_source(if i % 2 == 0 { AudioSource::USB } else { AudioSource::TUNER });
+            clock.advance(Duration::from_millis(100));
+            antenna.update(&state, clock.now());
+            if relay.get() != last {
+                toggles += 1;
+                last = relay.get();
+            }
+        }
+
+        // 4 seconds of flapping with a 2 second dwell allows at most two moves
+        assert!(toggles <= 2);
+
+        state.set_audio_source(AudioSource::USB);
+        clock.advance(Duration::from_secs(2));
+        antenna.update(&state, clock.now());
+        assert!(!relay.get());
+    }
+}
@@ -7,34 +7,42 @@ use alloc::format;
 use alloc::rc::Rc;
 use alloc::string::{ ToString};
 use core::cell::RefCell;
+use critical_section::Mutex;
 use esp_bootloader_esp_idf::esp_app_desc;
+use esp_hal::analog::adc::{Adc, AdcConfig, Attenuation};
 use esp_hal::clock::{Clock, CpuClock};
-use esp_hal::gpio::{Input, InputConfig, Level, Output, OutputConfig, Pull};
+use esp_hal::gpio::{Event, Input, InputConfig, Io, Level, Output, OutputConfig, Pull};
 use esp_hal::i2c::master as I2C;
 use esp_hal::system::software_reset;
 use esp_hal::time::Rate;
 use esp_hal::uart as UART;
-use esp_hal::xtensa_lx::timer::{delay, get_cycle_count};
-use esp_hal::{Blocking, Config, main};
+use esp_hal::xtensa_lx::timer::delay;
+use esp_hal::{Blocking, Config, handler, main, ram};
 use esp_println::{print, println};
+use embedded_hal_bus::i2c::RefCellDevice;
+use esp_storage::FlashStorage;
 
-mod encoder;
-use encoder::Encoder;
+mod system_clock;
+use system_clock::SystemClock;
 
-mod sh1122;
-use sh1122::Sh1122;
+#[cfg(feature = "spi-display")]
+mod spi_dma;
 
-mod display;
-use display::Display;
-
-mod screen;
-
-mod home;
-
-mod state;
-use state::State;
-use crate::screen::{InputEvent, Screen};
-use crate::state::ActiveScreen;
+use s40_hardware::antenna::AntennaController;
+use s40_hardware::clock::{Clock as _, Instant};
+use s40_hardware::display::Display;
+use s40_hardware::button::Button;
+use s40_hardware::encoder::Encoder;
+use s40_hardware::input::InputManager;
+use s40_hardware::ladder::{LadderCalibration, SteeringWheel};
+use s40_hardware::power::PowerController;
+use s40_hardware::power_monitor::{MonitorChip, PowerMonitor};
+use s40_hardware::settings_store::SettingsStore;
+use s40_hardware::sh1122::Sh1122;
+use s40_hardware::protocol::{self, Message, Parser};
+use s40_hardware::queue::{EventQueue, Producer};
+use s40_hardware::screen::{InputAction, InputEvent, PRIMARY_ENCODER, SECONDARY_ENCODER, SourceId};
+use s40_hardware::state::State;
 
 #[panic_handler]
 fn panic(info: &core::panic::PanicInfo) -> ! {
@@ -44,9 +52,50 @@ fn panic(info: &core::panic::PanicInfo) -> ! {
 
 esp_app_desc!();
 
-fn millis(config: &Config) -> u64 {
-    let cycles = get_cycle_count() as u64;
-    cycles * 1000 / (config.cpu_clock().hz() as u64)
+const EVENT_QUEUE_SIZE: usize = 32;
+
+// Momentary buttons on the dashboard
+const DASH_BUTTON_1: SourceId = 2;
+const DASH_BUTTON_2: SourceId = 3;
+
+// The `ladder` partition in partitions.csv, one sector for the steering wheel calibration
+const LADDER_CALIBRATION_OFFSET: u32 = 0x14000;
+
+// The `settings` partition in partitions.csv
+const SETTINGS_OFFSET: u32 = 0x10000;
+const SETTINGS_SIZE: u32 = 0x4000;
+
+// Filled from the GPIO interrupt, drained by the main loop
+static INPUT_EVENTS: EventQueue<InputEvent, EVENT_QUEUE_SIZE> = EventQueue::new();
+
+struct InputIrq {
+    inputs: InputManager<Input<'static>>,
+    events: Producer<'static, InputEvent, EVENT_QUEUE_SIZE>,
+}
+
+impl InputIrq {
+    fn update(&mut self, now: Instant) {
+        // A full queue means the main loop has stalled; the event is dropped
+        let events = &mut self.events;
+        self.inputs.update(now, |event| { events.push(event).ok(); });
+    }
+}
+
+static INPUTS: Mutex<RefCell<Option<InputIrq>>> = Mutex::new(RefCell::new(None));
+
+#[handler]
+#[ram]
+fn gpio_interrupt() {
+    critical_section::with(|cs| {
+        let mut inputs = INPUTS.borrow_ref_mut(cs);
+        let Some(irq) = inputs.as_mut() else { return; };
+
+        for pin in irq.inputs.pins_mut() {
+            pin.clear_interrupt();
+        }
+
+        irq.update(SystemClock.now());
+    });
 }
 
 fn delay_ms(config: &Config, mut ms: u64) {
@@ -59,6 +108,35 @@ fn delay_ms(config: &Config, mut ms: u64) {
     }
 }
 
+fn send_message(uart: &mut UART::Uart<'_, Blocking>, msg: Message) {
+    let mut out = [0u8; protocol::MAX_FRAME];
+    let Ok(len) = msg.encode(&mut out) else { return; };
+
+    let mut sent = 0;
+    while sent < len {
+        match uart.write(&out[sent..len]) {
+            Ok(n) => sent += n,
+            Err(_) => return,
+        }
+    }
+}
+
+fn poll_host_link(uart: &mut UART::Uart<'_, Blocking>, parser: &mut Parser, state: &State, now: Instant) {
+    let mut rx = [0u8; 64];
+    let Ok(len) = uart.read_buffered(&mut rx) else { return; };
+
+    let mut fed = 0;
+    while fed < len {
+        fed += parser.feed(&rx[fed..len]);
+
+        while let Some(result) = parser.next_frame() {
+            if let Some(reply) = protocol::handle(state, result, now) {
+                send_message(uart, reply);
+            }
+        }
+    }
+}
+
 #[main]
 fn main() -> ! {
     esp_alloc::heap_allocator!(size: 92 * 1024); // 92 KB heap
@@ -72,14 +150,47 @@ fn main() -> ! {
         .with_rx(peripherals.GPIO3);
 
     let i2c_config = I2C::Config::default().with_frequency(Rate::from_khz(400));
-    let mut i2c = I2C::I2c::new(peripherals.I2C0, i2c_config)
+    let i2c = I2C::I2c::new(peripherals.I2C0, i2c_config)
         .unwrap()
         .with_sda(peripherals.GPIO21)
         .with_scl(peripherals.GPIO22);
 
-    let driver = Sh1122::new(&mut i2c, 0x3C);
+    // Every I2C0 device gets its own handle onto the one bus
+    let i2c_bus = RefCell::new(i2c);
+
+    #[cfg(not(feature = "spi-display"))]
+    let driver = Sh1122::new_i2c(RefCellDevice::new(&i2c_bus), 0x3C);
+
+    #[cfg(feature = "spi-display")]
+    let driver = {
+        use esp_hal::spi::master::{Config as SpiConfig, Spi};
+        use esp_hal::spi::Mode;
+
+        let spi_config = SpiConfig::default()
+            .with_frequency(Rate::from_mhz(10))
+            .with_mode(Mode::_0);
+        let spi = Spi::new(peripherals.SPI2, spi_config)
+            .unwrap()
+            .with_sck(peripherals.GPIO14)
+            .with_mosi(peripherals.GPIO13)
+            .with_cs(peripherals.GPIO27)
+            .with_dma(peripherals.DMA_SPI2);
+        let buffer = esp_hal::dma_tx_buffer!(128).unwrap();
+        let dc = Output::new(peripherals.GPIO26, Level::High, OutputConfig::default());
+
+        Sh1122::new(spi_dma::SpiDmaTransport::new(spi, buffer, dc))
+    };
+
     let mut display = Display::new(driver);
 
+    let mut power_monitor = PowerMonitor::new(
+        RefCellDevice::new(&i2c_bus),
+        0x40,
+        MonitorChip::INA219,
+        0.1,
+        3.2,
+    );
+
     let power_relay_pin = Output::new(
         peripherals.GPIO16,
         Level::High,
@@ -88,7 +199,7 @@ fn main() -> ! {
 
     let antenna_relay_pin = Output::new(
         peripherals.GPIO15,
-        Level::High,
+        Level::Low,
         OutputConfig::default(),
     );
 
@@ -97,47 +208,124 @@ fn main() -> ! {
         InputConfig::default().with_pull(Pull::Up),
     );
 
-    let encoder_0a_pin = Input::new(
-        peripherals.GPIO19,
-        InputConfig::default().with_pull(Pull::Up),
-    );
-    let encoder_0b_pin = Input::new(
-        peripherals.GPIO18,
-        InputConfig::default().with_pull(Pull::Up),
+    let mut io = Io::new(peripherals.IO_MUX);
+    io.set_interrupt_handler(gpio_interrupt);
+
+    let pull_up = || InputConfig::default().with_pull(Pull::Up);
+
+    let primary_encoder = Encoder::new(
+        Input::new(peripherals.GPIO19, pull_up()),
+        Input::new(peripherals.GPIO18, pull_up()),
+        Input::new(peripherals.GPIO17, pull_up()),
     );
-    let encoder_0c_pin = Input::new(
-        peripherals.GPIO17,
-        InputConfig::default().with_pull(Pull::Up),
+    let secondary_encoder = Encoder::new(
+        Input::new(peripherals.GPIO32, pull_up()),
+        Input::new(peripherals.GPIO33, pull_up()),
+        Input::new(peripherals.GPIO25, pull_up()),
     );
 
+    let mut inputs = InputManager::new()
+        .with_encoder(PRIMARY_ENCODER, primary_encoder)
+        .with_encoder(SECONDARY_ENCODER, secondary_encoder)
+        .with_button(DASH_BUTTON_1, Button::new(Input::new(peripherals.GPIO4, pull_up())))
+        .with_button(DASH_BUTTON_2, Button::new(Input::new(peripherals.GPIO23, pull_up())));
+
+    // Steering wheel buttons: resistor ladder on GPIO36 (ADC1)
+    let mut flash = FlashStorage::new(peripherals.FLASH);
+    let calibration = LadderCalibration::load(&mut flash, LADDER_CALIBRATION_OFFSET).unwrap_or_default();
+    let mut adc_config = AdcConfig::new();
+    let mut wheel_pin = adc_config.enable_pin(peripherals.GPIO36, Attenuation::_11dB);
+    let mut adc = Adc::new(peripherals.ADC1, adc_config);
+    let mut wheel = SteeringWheel::new(calibration);
+
+    let clock = SystemClock;
+
     let state = Rc::new(RefCell::new(State::new()));
 
-    let mut encoder_0 = Encoder::new(encoder_0a_pin, encoder_0b_pin, encoder_0c_pin)
-        .with_cw_callback({
-            let state = Rc::clone(&state);
-            move || {
-                let s = state.borrow_mut();
-                s.set_volume((s.volume() + 2).min(100));
-            }
-        })
-        .with_ccw_callback({
-            let state = Rc::clone(&state);
-            move || {
-                let s = state.borrow_mut();
-                s.set_volume(s.volume().saturating_sub(2));
+    let mut settings = SettingsStore::new(SETTINGS_OFFSET, SETTINGS_SIZE);
+    if let Some(saved) = settings.load(&mut flash) {
+        saved.apply(&state.borrow());
+    }
+    let mut acc_was_on = false;
+    let mut host_parser = Parser::new();
+
+    let mut power = PowerController::new(acc_pin, power_relay_pin);
+    let mut antenna = AntennaController::new(antenna_relay_pin);
+
+    let (events_tx, mut events) = INPUT_EVENTS.split().unwrap();
+
+    critical_section::with(|cs| {
+        for pin in inputs.pins_mut() {
+            pin.listen(Event::AnyEdge);
+        }
+
+        INPUTS.borrow_ref_mut(cs).replace(InputIrq { inputs, events: events_tx });
+    });
+
+    loop {
+        poll_host_link(&mut uart, &mut host_parser, &state.borrow(), clock.now());
+
+        power.update(&state.borrow(), clock.now());
+
+        // Save right away when the ignition goes off, in case our own supply goes with it
+        let saved = if acc_was_on && !power.acc_on() {
+            settings.flush(&mut flash, &state.borrow())
+        } else {
+            settings.update(&mut flash, &state.borrow(), clock.now())
+        };
+        if let Err(e) = saved {
+            println!("Failed to save settings: {:?}", e);
+        }
+        acc_was_on = power.acc_on();
+        antenna.update(&state.borrow(), clock.now());
+        power_monitor.update(&state.borrow(), clock.now()).ok();
+
+        // Button gestures complete on timeouts as well as on edges
+        critical_section::with(|cs| {
+            if let Some(irq) = INPUTS.borrow_ref_mut(cs).as_mut() {
+                irq.update(clock.now());
             }
-        })
-        .with_button_callback({
-            move || { /* optional button logic */ }
         });
 
-    loop {
-        let time_passed = millis(&system_config);
+        if let Ok(reading) = adc.read_oneshot(&mut wheel_pin) {
+            wheel.update(reading, clock.now(), |event| display.handle_event(&state.borrow(), event));
+        }
+
+        if let Some(calibration) = wheel.take_learned() {
+            match calibration.save(&mut flash, LADDER_CALIBRATION_OFFSET) {
+                Ok(()) => println!("Steering wheel calibration saved"),
+                Err(e) => println!("Failed to save steering wheel calibration: {:?}", e),
+            }
+        }
+
+        while let Some(event) = events.pop() {
+            // Holding the first dash button relearns the steering wheel keys
+            if event == InputEvent::new(DASH_BUTTON_1, InputAction::LongPress) {
+                println!("Learning steering wheel: release all keys, then press each in turn");
+                wheel.start_learning();
+                continue;
+            }
+
+            // Any other input while learning abandons it, though not the repeats of a key still held
+            if wheel.is_learning() && event.action != InputAction::Repeat {
+                println!("Steering wheel learning cancelled, keeping the old calibration");
+                wheel.cancel_learning();
+                continue;
+            }
+
+            display.handle_event(&state.borrow(), event);
+        }
 
-        encoder_0.update();
+        // Tell the host about sources and track skips picked on the device so it follows
+        while let Some(command) = protocol::pending_command(&state.borrow()) {
+            send_message(&mut uart, command);
+        }
 
-        display.update(&state.borrow(), time_passed);
+        display.update(&state.borrow(), clock.now());
 
-        delay_ms(&system_config, 5);
+        // Keep feeding the DMA engine rows while a frame is going out
+        if !display.is_flushing() {
+            delay_ms(&system_config, 5);
+        }
     }
 }
@@ -0,0 +1,190 @@
+// Host-side display simulator: runs the real screens and `Display` against a scripted
+// timeline and writes what the SH1122 would have shown as PNG frames or an animated GIF.
+
+mod output;
+mod panel;
+mod script;
+
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use s40_hardware::clock::{Clock, Duration, Instant, MockClock};
+use s40_hardware::display::Display;
+use s40_hardware::framebuffer::{HEIGHT, WIDTH};
+use s40_hardware::protocol;
+use s40_hardware::state::State;
+
+use output::GifWriter;
+use panel::SimPanel;
+use script::{Step, Timed};
+
+// Same pace as the firmware main loop
+const TICK: Duration = Duration::from_millis(5);
+// How long to keep running after the last step when the script has no `end`
+const RUN_OUT: Duration = Duration::from_secs(2);
+
+const DEMO: &str = include_str!("demo.txt");
+
+const USAGE: &str = "\
+Usage: cargo simulate -- [options]
+
+Options:
+  --script <file>   timeline to play (default: built-in demo)
+  --png <dir>       write each new frame as <dir>/frame_<ms>.png
+  --gif <file>      write an animated GIF
+  --fps <n>         frames captured per second (default: 25)
+  --scale <n>       pixel size in the output (default: 2)
+  --length <time>   stop after this long, e.g. 8s (default: at `end`, or 2 s after the last step)
+
+At least one of --png and --gif is needed. The script format is described in script.rs.";
+
+struct Options {
+    script: Option<PathBuf>,
+    png: Option<PathBuf>,
+    gif: Option<PathBuf>,
+    fps: u32,
+    scale: usize,
+    length: Option<Duration>,
+}
+
+fn parse_args() -> Result<Options, String> {
+    let mut options = Options {
+        script: None,
+        png: None,
+        gif: None,
+        fps: 25,
+        scale: 2,
+        length: None,
+    };
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or(format!("{} needs a value", arg));
+
+        match arg.as_str() {
+            "--script" => options.script = Some(value()?.into()),
+            "--png" => options.png = Some(value()?.into()),
+            "--gif" => options.gif = Some(value()?.into()),
+            "--fps" => options.fps = value()?.parse().ok().filter(|fps| (1..=100).contains(fps))
+                .ok_or("--fps takes a number from 1 to 100")?,
+            "--scale" => options.scale = value()?.parse().ok().filter(|scale| (1..=8).contains(scale))
+                .ok_or("--scale takes a number from 1 to 8")?,
+            "--length" => options.length = Some(script::parse_time(&value()?)?),
+            "--help" | "-h" => return Err(String::new()),
+            other => return Err(format!("unknown option `{}`", other)),
+        }
+    }
+
+    if options.png.is_none() && options.gif.is_none() {
+        return Err("nothing to write: pass --png and/or --gif".into());
+    }
+
+    Ok(options)
+}
+
+fn run(options: Options) -> Result<(), String> {
+    let text = match &options.script {
+        Some(path) => std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?,
+        None => DEMO.to_string(),
+    };
+    let steps = script::parse(&text)?;
+
+    let end = options.length
+        .or_else(|| steps.iter().find(|timed| timed.step == Step::End).map(|timed| timed.at))
+        .unwrap_or_else(|| steps.last().map_or(Duration::ZERO, |timed| timed.at) + RUN_OUT);
+
+    let (width, height) = (WIDTH * options.scale, HEIGHT * options.scale);
+    if let Some(dir) = &options.png {
+        std::fs::create_dir_all(dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+    }
+    let mut gif = options.gif.as_deref()
+        .map(|path| GifWriter::create(path, width, height))
+        .transpose()?;
+
+    let clock = MockClock::new();
+    let state = State::new();
+    let mut display = Display::new(SimPanel::new());
+    let mut steps = steps.into_iter().peekable();
+
+    let frame_interval = Duration::from_millis(1000 / options.fps as u64);
+    let mut next_frame = Duration::ZERO;
+    let mut last_png: Option<Vec<u8>> = None;
+    let mut frames = 0;
+
+    loop {
+        let now = clock.now();
+        let elapsed = now.duration_since(Instant::default());
+        if elapsed > end {
+            break;
+        }
+
+        while let Some(Timed { step, .. }) = steps.next_if(|timed| timed.at <= elapsed) {
+            match step {
+                Step::Input(event) => display.handle_event(&state, event),
+                step => step.apply(&state, now),
+            }
+        }
+
+        // Commands the firmware would send over the host link
+        while let Some(command) = protocol::pending_command(&state) {
+            println!("{:>7} ms  to host: {:?}", elapsed.as_millis(), command);
+        }
+
+        display.update(&state, now);
+
+        if elapsed >= next_frame {
+            next_frame += frame_interval;
+            frames += 1;
+            let levels = display.driver().levels(options.scale);
+
+            if let Some(dir) = &options.png
+                && last_png.as_ref() != Some(&levels)
+            {
+                let path = dir.join(format!("frame_{:06}.png", elapsed.as_millis()));
+                output::write_png(&path, &levels, width, height)?;
+                last_png = Some(levels.clone());
+            }
+
+            if let Some(gif) = gif.as_mut() {
+                gif.push(levels, (frame_interval.as_millis() / 10) as u16)?;
+            }
+        }
+
+        clock.advance(TICK);
+    }
+
+    if let Some(gif) = gif {
+        gif.finish()?;
+    }
+
+    println!(
+        "Simulated {:.1} s: {} frames captured, {} panel flushes",
+        end.as_secs_f32(),
+        frames,
+        display.driver().flushes()
+    );
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let options = match parse_args() {
+        Ok(options) => options,
+        // Asked for help
+        Err(e) if e.is_empty() => {
+            println!("{}", USAGE);
+            return ExitCode::SUCCESS;
+        }
+        Err(e) => {
+            eprintln!("error: {}\n\n{}", e, USAGE);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    match run(options) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
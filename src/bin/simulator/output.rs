@@ -0,0 +1,81 @@
+// Writes captured frames, given as grey levels 0-15, to PNG files or an animated GIF.
+
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+
+use gif::{Encoder, Frame, Repeat};
+
+pub fn write_png(path: &Path, levels: &[u8], width: usize, height: usize) -> Result<(), String> {
+    let file = File::create(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+
+    let mut encoder = png::Encoder::new(BufWriter::new(file), width as u32, height as u32);
+    encoder.set_color(png::ColorType::Grayscale);
+    encoder.set_depth(png::BitDepth::Eight);
+
+    let grey: Vec<u8> = levels.iter().map(|level| level * 17).collect();
+    encoder.write_header()
+        .and_then(|mut writer| writer.write_image_data(&grey))
+        .map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+// Runs of identical frames are merged into one longer frame.
+pub struct GifWriter {
+    encoder: Encoder<BufWriter<File>>,
+    width: u16,
+    height: u16,
+    // Frame waiting to see how long it stays up, and for how long so far, in 10 ms units
+    pending: Option<(Vec<u8>, u16)>,
+}
+
+impl GifWriter {
+    pub fn create(path: &Path, width: usize, height: usize) -> Result<Self, String> {
+        let error = |e: &dyn std::fmt::Display| format!("{}: {}", path.display(), e);
+        let file = File::create(path).map_err(|e| error(&e))?;
+
+        // Palette entry n is grey level n
+        let palette: Vec<u8> = (0..16u8).flat_map(|level| [level * 17; 3]).collect();
+        let mut encoder = Encoder::new(BufWriter::new(file), width as u16, height as u16, &palette)
+            .map_err(|e| error(&e))?;
+        encoder.set_repeat(Repeat::Infinite).map_err(|e| error(&e))?;
+
+        Ok(GifWriter {
+            encoder,
+            width: width as u16,
+            height: height as u16,
+            pending: None,
+        })
+    }
+
+    pub fn push(&mut self, levels: Vec<u8>, delay_cs: u16) -> Result<(), String> {
+        if let Some((pending, delay)) = &mut self.pending
+            && *pending == levels
+        {
+            *delay = delay.saturating_add(delay_cs);
+            return Ok(());
+        }
+
+        self.write_pending()?;
+        self.pending = Some((levels, delay_cs));
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> Result<(), String> {
+        self.write_pending()
+    }
+
+    fn write_pending(&mut self) -> Result<(), String> {
+        let Some((levels, delay)) = self.pending.take() else {
+            return Ok(());
+        };
+
+        let frame = Frame {
+            width: self.width,
+            height: self.height,
+            delay,
+            buffer: levels.into(),
+            ..Frame::default()
+        };
+        self.encoder.write_frame(&frame).map_err(|e| e.to_string())
+    }
+}
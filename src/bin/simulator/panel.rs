@@ -0,0 +1,89 @@
+// Stand-in for the SH1122: draws into the same packed, bottom-up framebuffer and keeps what
+// the last flush "sent" as the picture on the glass.
+
+use std::convert::Infallible;
+
+use embedded_graphics::Pixel;
+use embedded_graphics::draw_target::DrawTarget;
+use embedded_graphics::geometry::{OriginDimensions, Size};
+use embedded_graphics::pixelcolor::Gray4;
+
+use s40_hardware::display::Panel;
+use s40_hardware::framebuffer::{FrameBuffer, HEIGHT, ROW_BYTES, WIDTH};
+
+pub struct SimPanel {
+    buffer: FrameBuffer,
+    shown: FrameBuffer,
+    flushes: u32,
+}
+
+impl SimPanel {
+    pub fn new() -> Self {
+        SimPanel {
+            buffer: FrameBuffer::new(),
+            shown: FrameBuffer::new(),
+            flushes: 0,
+        }
+    }
+
+    pub fn flushes(&self) -> u32 {
+        self.flushes
+    }
+
+    // What the panel shows as grey levels 0-15, top row first, each pixel repeated `scale`
+    // times each way. Decoded from the raw bytes the way the controller scans them, so a
+    // layout mistake in `FrameBuffer` shows up here too.
+    pub fn levels(&self, scale: usize) -> Vec<u8> {
+        let bytes = self.shown.as_bytes();
+        let mut out = Vec::with_capacity(WIDTH * HEIGHT * scale * scale);
+
+        for y in 0..HEIGHT {
+            // Rows are stored bottom-up
+            let row = &bytes[(HEIGHT - 1 - y) * ROW_BYTES..][..ROW_BYTES];
+            let line: Vec<u8> = row.iter()
+                .flat_map(|byte| [byte >> 4, byte & 0x0F])
+                .flat_map(|level| std::iter::repeat_n(level, scale))
+                .collect();
+
+            for _ in 0..scale {
+                out.extend_from_slice(&line);
+            }
+        }
+
+        out
+    }
+}
+
+impl DrawTarget for SimPanel {
+    type Color = Gray4;
+    type Error = Infallible;
+
+    fn draw_iter<P>(&mut self, pixels: P) -> Result<(), Self::Error>
+    where
+        P: IntoIterator<Item=Pixel<Self::Color>>
+    {
+        self.buffer.draw_iter(pixels)
+    }
+}
+
+impl OriginDimensions for SimPanel {
+    fn size(&self) -> Size {
+        self.buffer.size()
+    }
+}
+
+impl Panel for SimPanel {
+    fn init(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    fn clear(&mut self) {
+        self.buffer.clear();
+    }
+
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        self.shown = self.buffer.clone();
+        self.flushes += 1;
+        Ok(())
+    }
+}
@@ -0,0 +1,179 @@
+// Timeline of state changes and input for the simulator, one step per line:
+//
+//   <time> <command> [arguments]
+//
+// Times are milliseconds from the start, or seconds with an `s` suffix. Blank lines and
+// lines starting with `#` are ignored.
+//
+//   title <text>                  artist <text>
+//   volume <0-100>                power on|auto|off
+//   voltage <volts>               current <amps>
+//   playback <position> <duration> play|pause
+//   source tuner|bluetooth|aux|usb
+//   tuner <kHz> [station]         bt-device [name]
+//   turn <steps> [source]         press-turn <steps> [source]
+//   press [source]                long-press [source]          double-click [source]
+//   end
+//
+// Sources are primary, secondary, wheel-up, wheel-down, wheel-next, wheel-prev, wheel-mode
+// or a number, and default to primary.
+
+use s40_hardware::clock::{Duration, Instant};
+use s40_hardware::screen::{
+    InputAction, InputEvent, PRIMARY_ENCODER, SECONDARY_ENCODER, SourceId, WHEEL_MODE, WHEEL_NEXT,
+    WHEEL_PREV, WHEEL_VOLUME_DOWN, WHEEL_VOLUME_UP,
+};
+use s40_hardware::state::{AudioSource, PowerSetting, State};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Step {
+    Title(String),
+    Artist(String),
+    Volume(u32),
+    Power(PowerSetting),
+    Voltage(f32),
+    Current(f32),
+    Playback { position: Duration, duration: Duration, playing: bool },
+    Source(AudioSource),
+    Tuner { frequency_khz: u32, station: String },
+    BluetoothDevice(String),
+    Input(InputEvent),
+    End,
+}
+
+impl Step {
+    // Applies a state change. Input is left to the caller, which routes it through the display.
+    pub fn apply(&self, state: &State, now: Instant) {
+        match self {
+            Step::Title(title) => state.set_track_title(title),
+            Step::Artist(artist) => state.set_track_artist(artist),
+            Step::Volume(volume) => state.set_volume(*volume),
+            Step::Power(setting) => state.set_power_setting(*setting),
+            Step::Voltage(voltage) => state.set_voltage(*voltage),
+            Step::Current(current) => state.set_current(*current),
+            Step::Playback { position, duration, playing } => {
+                state.set_playback(*position, *duration, *playing, now)
+            }
+            Step::Source(source) => state.set_audio_source(*source),
+            Step::Tuner { frequency_khz, station } => state.set_tuner(*frequency_khz, station),
+            Step::BluetoothDevice(name) => state.set_bluetooth_device(name),
+            Step::Input(_) | Step::End => {}
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Timed {
+    pub at: Duration,
+    pub step: Step,
+}
+
+// Steps come back in time order; steps at the same time keep their order in the file.
+pub fn parse(text: &str) -> Result<Vec<Timed>, String> {
+    let mut steps = Vec::new();
+
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let timed = parse_line(line).map_err(|e| format!("line {}: {}", number + 1, e))?;
+        steps.push(timed);
+    }
+
+    steps.sort_by_key(|timed| timed.at);
+    Ok(steps)
+}
+
+fn parse_line(line: &str) -> Result<Timed, String> {
+    let (time, rest) = line.split_once(char::is_whitespace).ok_or("missing command")?;
+    let rest = rest.trim_start();
+    let (command, args) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+    let args = args.trim();
+    let words: Vec<&str> = args.split_whitespace().collect();
+
+    let step = match command {
+        "title" => Step::Title(args.to_string()),
+        "artist" => Step::Artist(args.to_string()),
+        "volume" => Step::Volume(number(&words, 0)?),
+        "power" => Step::Power(match words.first().copied() {
+            Some("on") => PowerSetting::ON,
+            Some("auto") => PowerSetting::AUTO,
+            Some("off") => PowerSetting::OFF,
+            _ => return Err("power takes on, auto or off".into()),
+        }),
+        "voltage" => Step::Voltage(number(&words, 0)?),
+        "current" => Step::Current(number(&words, 0)?),
+        "playback" => Step::Playback {
+            position: time_arg(&words, 0)?,
+            duration: time_arg(&words, 1)?,
+            playing: match words.get(2).copied() {
+                Some("play") => true,
+                Some("pause") => false,
+                _ => return Err("playback takes a position, a duration and play or pause".into()),
+            },
+        },
+        "source" => Step::Source(match words.first().copied() {
+            Some("tuner") => AudioSource::TUNER,
+            Some("bluetooth") => AudioSource::BLUETOOTH,
+            Some("aux") => AudioSource::AUX,
+            Some("usb") => AudioSource::USB,
+            _ => return Err("source takes tuner, bluetooth, aux or usb".into()),
+        }),
+        "tuner" => Step::Tuner {
+            frequency_khz: number(&words, 0)?,
+            station: args.split_once(char::is_whitespace).map_or("", |(_, station)| station.trim()).to_string(),
+        },
+        "bt-device" => Step::BluetoothDevice(args.to_string()),
+        "turn" => input(InputAction::Turn(number(&words, 0)?), words.get(1))?,
+        "press-turn" => input(InputAction::PressTurn(number(&words, 0)?), words.get(1))?,
+        "press" => input(InputAction::ShortPress, words.first())?,
+        "long-press" => input(InputAction::LongPress, words.first())?,
+        "double-click" => input(InputAction::DoubleClick, words.first())?,
+        "end" => Step::End,
+        other => return Err(format!("unknown command `{}`", other)),
+    };
+
+    Ok(Timed { at: parse_time(time)?, step })
+}
+
+fn input(action: InputAction, source: Option<&&str>) -> Result<Step, String> {
+    let source = match source.copied() {
+        None | Some("primary") => PRIMARY_ENCODER,
+        Some("secondary") => SECONDARY_ENCODER,
+        Some("wheel-up") => WHEEL_VOLUME_UP,
+        Some("wheel-down") => WHEEL_VOLUME_DOWN,
+        Some("wheel-next") => WHEEL_NEXT,
+        Some("wheel-prev") => WHEEL_PREV,
+        Some("wheel-mode") => WHEEL_MODE,
+        Some(other) => other.parse::<SourceId>().map_err(|_| format!("unknown source `{}`", other))?,
+    };
+
+    Ok(Step::Input(InputEvent::new(source, action)))
+}
+
+fn number<T: std::str::FromStr>(words: &[&str], index: usize) -> Result<T, String> {
+    let word = words.get(index).ok_or("missing argument")?;
+    word.parse().map_err(|_| format!("`{}` is not a number", word))
+}
+
+fn time_arg(words: &[&str], index: usize) -> Result<Duration, String> {
+    parse_time(words.get(index).ok_or("missing time")?)
+}
+
+pub fn parse_time(text: &str) -> Result<Duration, String> {
+    let invalid = || format!("`{}` is not a time", text);
+
+    if let Some(millis) = text.strip_suffix("ms") {
+        millis.parse().map(Duration::from_millis).map_err(|_| invalid())
+    } else if let Some(secs) = text.strip_suffix('s') {
+        let secs: f64 = secs.parse().map_err(|_| invalid())?;
+        if secs < 0.0 {
+            return Err(invalid());
+        }
+        Ok(Duration::from_secs_f64(secs))
+    } else {
+        text.parse().map(Duration::from_millis).map_err(|_| invalid())
+    }
+}
@@ -0,0 +1,92 @@
+// SH1122 transport over SPI2 with DMA: pixel rows are handed to the DMA engine and the main
+// loop carries on while they go out. Commands are short, so they are sent and waited for.
+
+use core::mem;
+use embedded_hal::spi::ErrorKind;
+use esp_hal::Blocking;
+use esp_hal::dma::DmaTxBuf;
+use esp_hal::gpio::Output;
+use esp_hal::spi::master::{SpiDma, SpiDmaTransfer};
+
+use s40_hardware::transport::{Sh1122Error, Transport};
+
+enum Channel<'d> {
+    Idle(SpiDma<'d, Blocking>, DmaTxBuf),
+    Busy(SpiDmaTransfer<'d, Blocking, DmaTxBuf>),
+    // Only seen while switching between the two above
+    Taken,
+}
+
+pub struct SpiDmaTransport<'d> {
+    channel: Channel<'d>,
+    dc: Output<'d>,
+}
+
+impl<'d> SpiDmaTransport<'d> {
+    pub fn new(spi: SpiDma<'d, Blocking>, buffer: DmaTxBuf, dc: Output<'d>) -> Self {
+        SpiDmaTransport {
+            channel: Channel::Idle(spi, buffer),
+            dc,
+        }
+    }
+
+    fn start(&mut self, bytes: &[u8]) -> Result<(), Sh1122Error> {
+        self.wait();
+
+        let Channel::Idle(spi, mut buffer) = mem::replace(&mut self.channel, Channel::Taken) else {
+            unreachable!();
+        };
+
+        buffer.fill(bytes);
+        match spi.write(bytes.len(), buffer) {
+            Ok(transfer) => {
+                self.channel = Channel::Busy(transfer);
+                Ok(())
+            }
+            Err((_, spi, buffer)) => {
+                self.channel = Channel::Idle(spi, buffer);
+                Err(Sh1122Error::Spi(ErrorKind::Other))
+            }
+        }
+    }
+
+    fn wait(&mut self) {
+        if let Channel::Busy(transfer) = mem::replace(&mut self.channel, Channel::Taken) {
+            let (spi, buffer) = transfer.wait();
+            self.channel = Channel::Idle(spi, buffer);
+        }
+    }
+}
+
+impl Transport for SpiDmaTransport<'_> {
+    fn command(&mut self, cmd: &[u8]) -> Result<(), Sh1122Error> {
+        self.wait();
+        self.dc.set_low();
+        self.start(cmd)?;
+        self.wait();
+        Ok(())
+    }
+
+    fn data(&mut self, data: &[u8]) -> Result<(), Sh1122Error> {
+        self.start_data(data)?;
+        self.wait();
+        Ok(())
+    }
+
+    fn start_data(&mut self, data: &[u8]) -> Result<(), Sh1122Error> {
+        self.wait();
+        self.dc.set_high();
+        self.start(data)
+    }
+
+    fn poll(&mut self) -> Result<bool, Sh1122Error> {
+        if let Channel::Busy(transfer) = &self.channel
+            && !transfer.is_done()
+        {
+            return Ok(false);
+        }
+
+        self.wait();
+        Ok(true)
+    }
+}
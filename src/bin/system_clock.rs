@@ -0,0 +1,11 @@
+use s40_hardware::clock::{Clock, Instant};
+
+// Monotonic clock backed by the esp-hal system timer, which counts in 64 bits.
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        let since_boot = esp_hal::time::Instant::now().duration_since_epoch();
+        Instant::from_micros(since_boot.as_micros())
+    }
+}
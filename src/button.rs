@@ -0,0 +1,418 @@
+use embedded_hal::digital::InputPin;
+use crate::clock::{Duration, Instant};
+use crate::screen::InputAction;
+
+// Thresholds for telling button gestures apart.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct GestureTiming {
+    // How long the pin has to hold a new level before it counts
+    pub debounce: Duration,
+    // Holding at least this long is a long press rather than a short one
+    pub long_press: Duration,
+    // Time between repeats while a long press is held, zero for no repeats
+    pub repeat_interval: Duration,
+    // A second press starting within this window after a release makes a double click
+    pub double_click: Duration,
+}
+
+impl Default for GestureTiming {
+    fn default() -> Self {
+        GestureTiming {
+            debounce: Duration::from_millis(20),
+            long_press: Duration::from_millis(600),
+            repeat_interval: Duration::from_millis(150),
+            double_click: Duration::from_millis(300),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Gesture {
+    Idle,
+    // `second` is set when this press follows a click within the double click window.
+    // `consumed` once the press has produced a long press or been used to rotate.
+    // `repeat_at` is when the next repeat is due, after a long press.
+    Down { since: Instant, second: bool, consumed: bool, repeat_at: Option<Instant> },
+    // Released after a short press; waiting to see whether a second press follows
+    Released { at: Instant },
+}
+
+// Debounced push button reporting short press, long press, repeats while held and double click.
+pub struct Button<P>
+where
+    P: InputPin
+{
+    pin: P,
+    active_low: bool,
+    gestures: Gestures,
+}
+
+impl<P> Button<P>
+where
+    P: InputPin
+{
+    pub fn new(pin: P) -> Self {
+        Button {
+            pin,
+            active_low: true,
+            gestures: Gestures::new(GestureTiming::default()),
+        }
+    }
+
+    pub fn with_active_low(mut self, active_low: bool) -> Self {
+        self.active_low = active_low;
+        self
+    }
+
+    pub fn with_timing(mut self, timing: GestureTiming) -> Self {
+        self.gestures = Gestures::new(timing);
+        self
+    }
+
+    // Debounced level
+    pub fn is_pressed(&self) -> bool {
+        self.gestures.is_pressed()
+    }
+
+    pub fn pin_mut(&mut self) -> &mut P {
+        &mut self.pin
+    }
+
+    // Samples the pin and advances the gesture timers. Call on every pin edge and
+    // periodically, since long presses and single clicks complete without an edge.
+    pub fn update<F>(&mut self, now: Instant, emit: F)
+    where
+        F: FnMut(InputAction)
+    {
+        // A failed read counts as no change, so the timers still run
+        let raw = match self.pin.is_high() {
+            Ok(high) => high != self.active_low,
+            Err(_) => self.gestures.raw,
+        };
+
+        self.gestures.update(raw, now, emit);
+    }
+
+    // See `Gestures::rotated`.
+    pub fn rotated<F>(&mut self, emit: F) -> bool
+    where
+        F: FnMut(InputAction)
+    {
+        self.gestures.rotated(emit)
+    }
+}
+
+// Debounce and gesture timing for anything that reads as pressed or not, independent of
+// where the level comes from.
+//
+// A short press is only reported once the double click window has passed without a second
+// press, so it never fires ahead of a double click.
+pub struct Gestures {
+    timing: GestureTiming,
+    raw: bool,
+    raw_since: Option<Instant>,
+    pressed: bool,
+    gesture: Gesture,
+}
+
+impl Gestures {
+    pub fn new(timing: GestureTiming) -> Self {
+        Gestures {
+            timing,
+            raw: false,
+            raw_since: None,
+            pressed: false,
+            gesture: Gesture::Idle,
+        }
+    }
+
+    // Debounced level
+    pub fn is_pressed(&self) -> bool {
+        self.pressed
+    }
+
+    // Feeds the current, undebounced level and advances the timers.
+    pub fn update<F>(&mut self, raw: bool, now: Instant, mut emit: F)
+    where
+        F: FnMut(InputAction)
+    {
+        if self.sample(raw, now) {
+            if self.pressed {
+                self.on_press(now);
+            } else {
+                self.on_release(now, &mut emit);
+            }
+        }
+
+        match self.gesture {
+            Gesture::Down { since, second, consumed: false, .. }
+                if now.duration_since(since) >= self.timing.long_press =>
+            {
+                if second {
+                    emit(InputAction::ShortPress);
+                }
+                emit(InputAction::LongPress);
+                let repeat_at = (!self.timing.repeat_interval.is_zero()).then(|| now + self.timing.repeat_interval);
+                self.gesture = Gesture::Down { since, second: false, consumed: true, repeat_at };
+            }
+            Gesture::Down { since, repeat_at: Some(at), .. } if now >= at => {
+                emit(InputAction::Repeat);
+                let repeat_at = Some(at + self.timing.repeat_interval);
+                self.gesture = Gesture::Down { since, second: false, consumed: true, repeat_at };
+            }
+            Gesture::Released { at } if now.duration_since(at) >= self.timing.double_click => {
+                emit(InputAction::ShortPress);
+                self.gesture = Gesture::Idle;
+            }
+            _ => {}
+        }
+    }
+
+    // Called when the knob turns. Returns whether the button is held, making it a
+    // press-and-rotate; a click still waiting for its double click window is settled first.
+    pub fn rotated<F>(&mut self, mut emit: F) -> bool
+    where
+        F: FnMut(InputAction)
+    {
+        match self.gesture {
+            Gesture::Down { since, second, .. } => {
+                if second {
+                    emit(InputAction::ShortPress);
+                }
+                self.gesture = Gesture::Down { since, second: false, consumed: true, repeat_at: None };
+                true
+            }
+            Gesture::Released { .. } => {
+                emit(InputAction::ShortPress);
+                self.gesture = Gesture::Idle;
+                false
+            }
+            Gesture::Idle => false,
+        }
+    }
+
+    // Returns true when the debounced level changed.
+    fn sample(&mut self, raw: bool, now: Instant) -> bool {
+        // The level at boot is taken as is; a button held during boot is not a press
+        let Some(raw_since) = self.raw_since else {
+            self.raw = raw;
+            self.raw_since = Some(now);
+            self.pressed = raw;
+            if raw {
+                self.gesture = Gesture::Down { since: now, second: false, consumed: true, repeat_at: None };
+            }
+            return false;
+        };
+
+        if raw != self.raw {
+            self.raw = raw;
+            self.raw_since = Some(now);
+            return false;
+        }
+
+        if self.raw != self.pressed && now.duration_since(raw_since) >= self.timing.debounce {
+            self.pressed = self.raw;
+            return true;
+        }
+
+        false
+    }
+
+    fn on_press(&mut self, now: Instant) {
+        let second = matches!(self.gesture, Gesture::Released { .. });
+        self.gesture = Gesture::Down { since: now, second, consumed: false, repeat_at: None };
+    }
+
+    fn on_release<F>(&mut self, now: Instant, emit: &mut F)
+    where
+        F: FnMut(InputAction)
+    {
+        self.gesture = match self.gesture {
+            Gesture::Down { consumed: true, .. } => Gesture::Idle,
+            Gesture::Down { second: true, .. } => {
+                emit(InputAction::DoubleClick);
+                Gesture::Idle
+            }
+            Gesture::Down { .. } if self.timing.double_click.is_zero() => {
+                emit(InputAction::ShortPress);
+                Gesture::Idle
+            }
+            Gesture::Down { .. } => Gesture::Released { at: now },
+            gesture => gesture,
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec::Vec;
+    use crate::clock::{Clock, MockClock};
+    use crate::mock::MockPin;
+
+    struct Harness {
+        button: Button<MockPin>,
+        pin: MockPin,
+        clock: MockClock,
+        events: Vec<InputAction>,
+    }
+
+    impl Harness {
+        fn new(timing: GestureTiming) -> Self {
+            // Active low with a pull-up: released reads high
+            let pin = MockPin::new(true);
+            let button = Button::new(pin.clone()).with_timing(timing);
+            let mut h = Harness { button, pin, clock: MockClock::new(), events: Vec::new() };
+            h.run_for(0);
+            h
+        }
+
+        fn run_for(&mut self, ms: u64) {
+            for _ in 0..ms {
+                self.button.update(self.clock.now(), |e| self.events.push(e));
+                self.clock.advance(Duration::from_millis(1));
+            }
+            self.button.update(self.clock.now(), |e| self.events.push(e));
+        }
+
+        // Plays a script of (pressed, milliseconds) pairs
+        fn play(&mut self, script: &[(bool, u64)]) {
+            for &(pressed, ms) in script {
+                self.pin.set(!pressed);
+                self.run_for(ms);
+            }
+        }
+    }
+
+    #[test]
+    fn bouncy_press_is_one_short_press() {
+        let mut h = Harness::new(GestureTiming::default());
+        h.play(&[
+            (true, 2), (false, 1), (true, 3), (false, 2), (true, 150),
+            (false, 1), (true, 1), (false, 5),
+        ]);
+        assert!(h.events.is_empty());
+
+        // Reported once the double click window has passed
+        h.run_for(400);
+        assert_eq!(h.events, [InputAction::ShortPress]);
+    }
+
+    #[test]
+    fn glitches_shorter_than_debounce_are_ignored() {
+        let mut h = Harness::new(GestureTiming::default());
+        for _ in 0..20 {
+            h.play(&[(true, 5), (false, 5)]);
+        }
+        h.run_for(1000);
+        assert!(h.events.is_empty());
+    }
+
+    #[test]
+    fn long_press_fires_while_held_and_not_on_release() {
+        let mut h = Harness::new(GestureTiming::default());
+        h.play(&[(true, 500)]);
+        assert!(h.events.is_empty());
+
+        h.play(&[(true, 150)]);
+        assert_eq!(h.events, [InputAction::LongPress]);
+
+        h.play(&[(false, 1000)]);
+        assert_eq!(h.events, [InputAction::LongPress]);
+    }
+
+    #[test]
+    fn holding_on_repeats_after_the_long_press() {
+        let mut h = Harness::new(GestureTiming::default());
+        h.play(&[(true, 700)]);
+        assert_eq!(h.events, [InputAction::LongPress]);
+
+        // The long press lands at 620 ms with the debounce, then every 150 ms
+        h.play(&[(true, 400)]);
+        assert_eq!(h.events, [InputAction::LongPress, InputAction::Repeat, InputAction::Repeat, InputAction::Repeat]);
+
+        h.play(&[(false, 1000)]);
+        assert_eq!(h.events.len(), 4);
+    }
+
+    #[test]
+    fn rotating_stops_the_repeats() {
+        let mut h = Harness::new(GestureTiming::default());
+        h.play(&[(true, 800)]);
+        assert_eq!(h.events, [InputAction::LongPress, InputAction::Repeat]);
+
+        assert!(h.button.rotated(|e| h.events.push(e)));
+        h.play(&[(true, 1000), (false, 100)]);
+        assert_eq!(h.events, [InputAction::LongPress, InputAction::Repeat]);
+    }
+
+    #[test]
+    fn two_quick_clicks_are_a_double_click() {
+        let mut h = Harness::new(GestureTiming::default());
+        h.play(&[(true, 80), (false, 120), (true, 80), (false, 1000)]);
+        assert_eq!(h.events, [InputAction::DoubleClick]);
+    }
+
+    #[test]
+    fn slow_clicks_are_two_short_presses() {
+        let mut h = Harness::new(GestureTiming::default());
+        h.play(&[(true, 80), (false, 500), (true, 80), (false, 500)]);
+        assert_eq!(h.events, [InputAction::ShortPress, InputAction::ShortPress]);
+    }
+
+    #[test]
+    fn thresholds_are_configurable() {
+        let timing = GestureTiming {
+            debounce: Duration::from_millis(5),
+            long_press: Duration::from_millis(200),
+            repeat_interval: Duration::ZERO,
+            double_click: Duration::ZERO,
+        };
+        let mut h = Harness::new(timing);
+
+        // Without a double click window the press is reported on release
+        h.play(&[(true, 50), (false, 10)]);
+        assert_eq!(h.events, [InputAction::ShortPress]);
+
+        // And without a repeat interval a long press happens once however long it is held
+        h.play(&[(true, 1000), (false, 10)]);
+        assert_eq!(h.events, [InputAction::ShortPress, InputAction::LongPress]);
+
+        let mut h = Harness::new(GestureTiming { repeat_interval: Duration::from_millis(50), ..timing });
+        h.play(&[(true, 310), (false, 10)]);
+        assert_eq!(h.events, [InputAction::LongPress, InputAction::Repeat, InputAction::Repeat]);
+    }
+
+    #[test]
+    fn held_through_boot_is_not_a_press() {
+        let pin = MockPin::new(false);
+        let mut button = Button::new(pin.clone());
+        let clock = MockClock::new();
+        let mut events = Vec::new();
+
+        for _ in 0..1000 {
+            button.update(clock.now(), |e| events.push(e));
+            clock.advance(Duration::from_millis(1));
+        }
+        pin.set(true);
+        for _ in 0..1000 {
+            button.update(clock.now(), |e| events.push(e));
+            clock.advance(Duration::from_millis(1));
+        }
+        assert!(events.is_empty());
+    }
+
+    #[test]
+    fn rotating_while_held_consumes_the_press() {
+        let mut h = Harness::new(GestureTiming::default());
+        h.play(&[(true, 100)]);
+        assert!(h.button.rotated(|e| h.events.push(e)));
+        h.play(&[(true, 1000), (false, 1000)]);
+        assert!(h.events.is_empty());
+
+        // A click waiting on its double click window is settled by the turn
+        h.play(&[(true, 80), (false, 50)]);
+        assert!(!h.button.rotated(|e| h.events.push(e)));
+        assert_eq!(h.events, [InputAction::ShortPress]);
+    }
+}
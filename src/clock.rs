@@ -0,0 +1,109 @@
+use core::cell::Cell;
+use core::ops::{Add, Sub};
+
+pub use core::time::Duration;
+
+// Point in time measured in microseconds since boot. 64 bits of microseconds
+// lasts for hundreds of thousands of years, so it never wraps in practice.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct Instant {
+    micros: u64,
+}
+
+impl Instant {
+    pub const fn from_micros(micros: u64) -> Self {
+        Instant { micros }
+    }
+
+    pub const fn from_millis(millis: u64) -> Self {
+        Instant { micros: millis * 1000 }
+    }
+
+    pub const fn as_micros(&self) -> u64 {
+        self.micros
+    }
+
+    pub const fn as_millis(&self) -> u64 {
+        self.micros / 1000
+    }
+
+    // Time elapsed since `earlier`, or zero if `earlier` is actually later.
+    pub fn duration_since(&self, earlier: Instant) -> Duration {
+        Duration::from_micros(self.micros.saturating_sub(earlier.micros))
+    }
+}
+
+impl Add<Duration> for Instant {
+    type Output = Instant;
+
+    fn add(self, rhs: Duration) -> Instant {
+        Instant::from_micros(self.micros.saturating_add(rhs.as_micros() as u64))
+    }
+}
+
+impl Sub<Instant> for Instant {
+    type Output = Duration;
+
+    fn sub(self, rhs: Instant) -> Duration {
+        self.duration_since(rhs)
+    }
+}
+
+pub trait Clock {
+    fn now(&self) -> Instant;
+}
+
+// Manually driven clock for host tests and the simulator.
+#[derive(Default)]
+pub struct MockClock {
+    now: Cell<Instant>,
+}
+
+impl MockClock {
+    pub fn new() -> Self {
+        MockClock {
+            now: Cell::new(Instant::default()),
+        }
+    }
+
+    pub fn set(&self, now: Instant) {
+        self.now.set(now);
+    }
+
+    pub fn advance(&self, by: Duration) {
+        self.now.set(self.now.get() + by);
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> Instant {
+        self.now.get()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn instant_arithmetic() {
+        let a = Instant::from_millis(1500);
+        let b = a + Duration::from_millis(250);
+
+        assert_eq!(b.as_millis(), 1750);
+        assert_eq!(b - a, Duration::from_millis(250));
+        assert_eq!(a - b, Duration::ZERO);
+    }
+
+    #[test]
+    fn mock_clock_advances() {
+        let clock = MockClock::new();
+        assert_eq!(clock.now(), Instant::from_micros(0));
+
+        clock.advance(Duration::from_secs(20));
+        assert_eq!(clock.now().as_millis(), 20_000);
+
+        clock.set(Instant::from_millis(5));
+        assert_eq!(clock.now().as_micros(), 5000);
+    }
+}
@@ -0,0 +1,521 @@
+use alloc::format;
+use alloc::string::String;
+use embedded_graphics::mono_font::ascii::FONT_7X13_BOLD;
+use embedded_graphics::mono_font::MonoTextStyle;
+use embedded_graphics::pixelcolor::Gray4;
+use embedded_graphics::prelude::*;
+use embedded_graphics::primitives::{PrimitiveStyle, Rectangle};
+use embedded_graphics::text::{Alignment, Text};
+use crate::clock::{Duration, Instant};
+use crate::state::{ActiveScreen, State};
+use crate::screen::{InputEvent, Screen};
+
+pub const WIDTH: i32 = 255;
+pub const HEIGHT: i32 = 63;
+
+const REFRESH_INTERVAL: Duration = Duration::from_millis(200);
+// While the screen is animating
+const ANIMATION_INTERVAL: Duration = Duration::from_millis(40);
+const RETRY_BACKOFF_MIN: Duration = Duration::from_millis(100);
+const RETRY_BACKOFF_MAX: Duration = Duration::from_secs(5);
+
+pub trait Panel: DrawTarget<Color = Gray4> {
+    fn init(&mut self) -> Result<(), Self::Error>;
+
+    fn clear(&mut self);
+
+    fn flush(&mut self) -> Result<(), Self::Error>;
+
+    // Begins sending the drawn frame; the panel may be drawn into again straight away.
+    fn start_flush(&mut self) -> Result<(), Self::Error> {
+        self.flush()
+    }
+
+    // Moves a started flush along without blocking. Returns true once it has completed.
+    fn poll_flush(&mut self) -> Result<bool, Self::Error> {
+        Ok(true)
+    }
+}
+
+pub struct Display<P>
+where
+    P: Panel
+{
+    driver: P,
+    last_state: Option<State>,
+    last_display_update: Option<Instant>,
+    redraw_pending: bool,
+    animating: bool,
+    // A frame has been drawn but not handed to the panel yet
+    frame_ready: bool,
+    flushing: bool,
+    initialised: bool,
+    failures: u32,
+    retry_at: Option<Instant>,
+}
+
+impl<P> Display<P>
+where
+    P: Panel
+{
+    // The panel is initialised lazily on the first update, so a missing or
+    // misbehaving panel never stops the rest of the firmware from booting.
+    pub fn new(driver: P) -> Self {
+        Display {
+            driver,
+            last_state: None,
+            last_display_update: None,
+            redraw_pending: true,
+            animating: false,
+            frame_ready: false,
+            flushing: false,
+            initialised: false,
+            failures: 0,
+            retry_at: None,
+        }
+    }
+
+    pub fn update(&mut self, state: &State, now: Instant) {
+        if let Some(last_state) = self.last_state.as_ref() {
+            let (should_update, animating) = match &mut *state.current_screen() {
+                ActiveScreen::Home(screen) => (screen.update(last_state, state, now), screen.is_animating()),
+                ActiveScreen::Menu(screen) => (screen.update(last_state, state, now), screen.is_animating()),
+            };
+            self.animating = animating;
+
+            // Changes seen between refreshes are remembered so the throttle only delays them
+            self.redraw_pending |= last_state != state || should_update;
+        }
+
+        self.last_state = Some(state.clone());
+
+        if self.retry_at.is_some_and(|retry_at| now < retry_at) {
+            return;
+        }
+
+        if self.step(state, now).is_err() {
+            // Assume the panel lost its configuration and back off before redrawing from scratch
+            self.redraw_pending = true;
+            self.last_display_update = None;
+            self.frame_ready = false;
+            self.flushing = false;
+            self.initialised = false;
+            self.failures = self.failures.saturating_add(1);
+            self.retry_at = Some(now + retry_backoff(self.failures));
+        }
+    }
+
+    // The next frame is drawn while the previous one is still being sent, and handed over
+    // as soon as the panel is free.
+    fn step(&mut self, state: &State, now: Instant) -> Result<(), P::Error> {
+        if self.flushing && self.driver.poll_flush()? {
+            self.flush_done();
+        }
+
+        let interval = if self.animating { ANIMATION_INTERVAL } else { REFRESH_INTERVAL };
+        let due = self.last_display_update
+            .is_none_or(|last| now.duration_since(last) >= interval);
+
+        if due && self.redraw_pending && !self.frame_ready {
+            self.draw(state);
+            self.redraw_pending = false;
+            self.frame_ready = true;
+            self.last_display_update = Some(now);
+        }
+
+        if self.frame_ready && !self.flushing {
+            if !self.initialised {
+                self.driver.init()?;
+                self.initialised = true;
+            }
+
+            self.driver.start_flush()?;
+            self.frame_ready = false;
+            self.flushing = true;
+
+            if self.driver.poll_flush()? {
+                self.flush_done();
+            }
+        }
+
+        Ok(())
+    }
+
+    // Input goes to whichever screen is showing; its effect appears on the next update.
+    pub fn handle_event(&mut self, state: &State, input: InputEvent) {
+        let navigation = match &mut *state.current_screen() {
+            ActiveScreen::Home(screen) => screen.handle_event(state, input),
+            ActiveScreen::Menu(screen) => screen.handle_event(state, input),
+        };
+
+        // The screen underneath may look the same as the one that left, so always redraw
+        if state.navigate(navigation) {
+            self.redraw_pending = true;
+        }
+    }
+
+    fn flush_done(&mut self) {
+        self.flushing = false;
+        self.failures = 0;
+        self.retry_at = None;
+    }
+
+    fn draw(&mut self, state: &State) {
+        Panel::clear(&mut self.driver);
+
+        match &*state.current_screen() {
+            ActiveScreen::Home(screen) => screen.draw(state, &mut self.driver),
+            ActiveScreen::Menu(screen) => screen.draw(state, &mut self.driver),
+        }
+    }
+
+    // True while a frame is still being transferred to the panel.
+    pub fn is_flushing(&self) -> bool {
+        self.flushing
+    }
+
+    pub fn failures(&self) -> u32 {
+        self.failures
+    }
+
+    pub fn driver(&self) -> &P {
+        &self.driver
+    }
+}
+
+fn retry_backoff(failures: u32) -> Duration {
+    let backoff = RETRY_BACKOFF_MIN.saturating_mul(1 << failures.saturating_sub(1).min(16));
+    backoff.min(RETRY_BACKOFF_MAX)
+}
+
+pub fn truncate(s: String, max_len: usize) -> String {
+    if s.chars().count() > max_len {
+        let truncated: String = s.chars().take(max_len).collect();
+        truncated + "..."
+    } else {
+        s
+    }
+}
+
+// m:ss, or h:mm:ss from an hour up
+pub fn format_time(time: Duration) -> String {
+    let secs = time.as_secs();
+    if secs >= 3600 {
+        format!("{}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+    } else {
+        format!("{}:{:02}", secs / 60, secs % 60)
+    }
+}
+
+pub fn draw_bar<D>(target: &mut D, label: &str, value: f32, min: f32, max: f32, suffix: &str) where D: DrawTarget<Color = Gray4> {
+    let width = 256 - 9;
+    let height = 38;
+    let v = ((value - min) / (max - min)).clamp(0.0, 1.0);
+    let bar_width = (width as f32) * v;
+
+    Text::with_alignment(
+        label,
+        Point::new(4, 13),
+        MonoTextStyle::new(&FONT_7X13_BOLD, Gray4::new(15)),
+        Alignment::Left
+    ).draw(target).ok();
+
+    Text::with_alignment(
+        format!("{}{}", value, suffix).as_str(),
+        Point::new(WIDTH - 4, 13),
+        MonoTextStyle::new(&FONT_7X13_BOLD, Gray4::new(15)),
+        Alignment::Right
+    ).draw(target).ok();
+
+    Rectangle::new(Point::new(4, 20), Size::new(width, height))
+        .into_styled(PrimitiveStyle::with_fill(Gray4::new(4)))
+        .draw(target).ok();
+
+    Rectangle::new(Point::new(4, 20), Size::new(bar_width as u32, height))
+        .into_styled(PrimitiveStyle::with_fill(Gray4::new(15)))
+        .draw(target).ok();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::ToString;
+    use crate::clock::{Clock, MockClock};
+    use crate::framebuffer::FrameBuffer;
+    use crate::mock::{MockDmaTransport, MockI2c};
+    use crate::screen::{InputAction, PRIMARY_ENCODER};
+    use crate::sh1122::Sh1122;
+    use crate::state::AudioSource;
+    use embedded_hal::i2c::{ErrorKind, NoAcknowledgeSource};
+
+    struct MockPanel {
+        buffer: FrameBuffer,
+        flushes: u32,
+    }
+
+    impl DrawTarget for MockPanel {
+        type Color = Gray4;
+        type Error = core::convert::Infallible;
+
+        fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+        where
+            I: IntoIterator<Item=Pixel<Self::Color>>
+        {
+            self.buffer.draw_iter(pixels)
+        }
+    }
+
+    impl OriginDimensions for MockPanel {
+        fn size(&self) -> Size {
+            self.buffer.size()
+        }
+    }
+
+    impl Panel for MockPanel {
+        fn init(&mut self) -> Result<(), Self::Error> {
+            Ok(())
+        }
+
+        fn clear(&mut self) {
+            self.buffer.clear();
+        }
+
+        fn flush(&mut self) -> Result<(), Self::Error> {
+            self.flushes += 1;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn redraws_only_on_change_and_throttles() {
+        let mut display = Display::new(MockPanel { buffer: FrameBuffer::new(), flushes: 0 });
+        let state = State::new();
+
+        let clock = MockClock::new();
+
+        display.update(&state, clock.now());
+        assert_eq!(display.driver().flushes, 1);
+
+        clock.advance(Duration::from_millis(500));
+        display.update(&state, clock.now());
+        assert_eq!(display.driver().flushes, 1);
+
+        state.set_track_artist("Blur");
+        display.update(&state, clock.now());
+        assert_eq!(display.driver().flushes, 2);
+
+        // A change inside the refresh interval is delayed, not dropped
+        clock.advance(Duration::from_millis(50));
+        state.set_track_artist("Damon Albarn");
+        display.update(&state, clock.now());
+        assert_eq!(display.driver().flushes, 2);
+
+        clock.advance(Duration::from_millis(100));
+        display.update(&state, clock.now());
+        assert_eq!(display.driver().flushes, 2);
+
+        clock.advance(Duration::from_millis(100));
+        display.update(&state, clock.now());
+        assert_eq!(display.driver().flushes, 3);
+    }
+
+    #[test]
+    fn dispatches_input_to_the_active_screen() {
+        let mut display = Display::new(MockPanel { buffer: FrameBuffer::new(), flushes: 0 });
+        let state = State::new();
+        let clock = MockClock::new();
+
+        display.update(&state, clock.now());
+        display.handle_event(&state, InputEvent::new(PRIMARY_ENCODER, InputAction::Turn(1)));
+        assert_eq!(state.volume(), 52);
+
+        // The home screen shows its volume overlay for the change
+        clock.advance(Duration::from_millis(200));
+        display.update(&state, clock.now());
+        assert_eq!(display.driver().flushes, 2);
+        assert_eq!(display.driver().buffer.pixel(200, 40), 4);
+    }
+
+    #[test]
+    fn navigates_into_settings_and_back() {
+        let mut display = Display::new(MockPanel { buffer: FrameBuffer::new(), flushes: 0 });
+        let state = State::new();
+        let clock = MockClock::new();
+        let press = |action| InputEvent::new(PRIMARY_ENCODER, action);
+
+        display.update(&state, clock.now());
+        display.handle_event(&state, press(InputAction::ShortPress));
+        assert!(matches!(*state.current_screen(), ActiveScreen::Menu(_)));
+
+        clock.advance(Duration::from_millis(200));
+        display.update(&state, clock.now());
+        assert_eq!(display.driver().flushes, 2);
+
+        // Turning moves the menu cursor instead of the volume, and still redraws
+        display.handle_event(&state, InputEvent::new(PRIMARY_ENCODER, InputAction::Turn(1)));
+        assert_eq!(state.volume(), 50);
+        clock.advance(Duration::from_millis(200));
+        display.update(&state, clock.now());
+        assert_eq!(display.driver().flushes, 3);
+
+        display.handle_event(&state, press(InputAction::LongPress));
+        assert!(matches!(*state.current_screen(), ActiveScreen::Home(_)));
+        clock.advance(Duration::from_millis(200));
+        display.update(&state, clock.now());
+        assert_eq!(display.driver().flushes, 4);
+    }
+
+    #[test]
+    fn refreshes_faster_while_animating() {
+        let mut display = Display::new(MockPanel { buffer: FrameBuffer::new(), flushes: 0 });
+        let state = State::new();
+        state.set_audio_source(AudioSource::USB);
+        let clock = MockClock::new();
+
+        // The default title is too long to fit and starts scrolling after a pause
+        for _ in 0..300 {
+            display.update(&state, clock.now());
+            clock.advance(Duration::from_millis(5));
+        }
+        assert_eq!(display.driver().flushes, 1);
+
+        for _ in 0..200 {
+            display.update(&state, clock.now());
+            clock.advance(Duration::from_millis(5));
+        }
+        assert!(display.driver().flushes >= 20);
+    }
+
+    #[test]
+    fn recovers_from_bus_faults_with_backoff() {
+        let i2c = MockI2c::new();
+        let mut display = Display::new(Sh1122::new_i2c(i2c.clone(), 0x3C));
+        let state = State::new();
+        let clock = MockClock::new();
+
+        let nack = ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address);
+        i2c.bus().faults.extend([nack, nack, ErrorKind::Bus]);
+
+        display.update(&state, clock.now());
+        assert_eq!(display.failures(), 1);
+
+        // Still backing off: the bus is left alone
+        clock.advance(Duration::from_millis(50));
+        display.update(&state, clock.now());
+        assert_eq!(i2c.bus().faults.len(), 2);
+
+        clock.advance(Duration::from_millis(50));
+        display.update(&state, clock.now());
+        assert_eq!(display.failures(), 2);
+
+        // Second retry waits twice as long
+        clock.advance(Duration::from_millis(150));
+        display.update(&state, clock.now());
+        assert_eq!(display.failures(), 2);
+        clock.advance(Duration::from_millis(50));
+        display.update(&state, clock.now());
+        assert_eq!(display.failures(), 3);
+
+        clock.advance(Duration::from_millis(400));
+        display.update(&state, clock.now());
+        assert_eq!(display.failures(), 0);
+
+        // The panel is re-initialised before the frame is sent
+        let bus = i2c.bus();
+        assert_eq!(bus.writes[0].1, [0x00, 0xAE]);
+        assert_eq!(bus.writes.last().unwrap().1.len(), 1 + 256 / 2);
+    }
+
+    #[test]
+    fn reinitialises_after_flush_failure() {
+        let i2c = MockI2c::new();
+        let mut display = Display::new(Sh1122::new_i2c(i2c.clone(), 0x3C));
+        let state = State::new();
+        state.set_audio_source(AudioSource::USB);
+        let clock = MockClock::new();
+
+        display.update(&state, clock.now());
+        assert_eq!(display.failures(), 0);
+        i2c.bus().writes.clear();
+
+        state.set_track_title("Rhinestone Eyes");
+        i2c.bus().faults.push_back(ErrorKind::ArbitrationLoss);
+        clock.advance(Duration::from_millis(300));
+        display.update(&state, clock.now());
+        assert_eq!(display.failures(), 1);
+
+        clock.advance(Duration::from_millis(100));
+        display.update(&state, clock.now());
+        assert_eq!(display.failures(), 0);
+        assert_eq!(i2c.bus().writes[0].1, [0x00, 0xAE]);
+    }
+
+    #[test]
+    fn composes_next_frame_while_previous_is_in_flight() {
+        let dma = MockDmaTransport::new(1);
+        let mut display = Display::new(Sh1122::new(dma.clone()));
+        let state = State::new();
+        let clock = MockClock::new();
+
+        display.update(&state, clock.now());
+        assert!(display.is_flushing());
+
+        // Each update returns straight away, with the transfer still running in the background
+        clock.advance(Duration::from_millis(300));
+        state.set_track_title("Feel Good Inc.");
+        display.update(&state, clock.now());
+        assert!(display.is_flushing());
+        assert!(display.frame_ready);
+
+        while display.is_flushing() {
+            display.update(&state, clock.now());
+        }
+
+        // The queued frame went out right behind the first (a command and data write for each
+        // of the 64 rows), as a partial update
+        assert!(!display.frame_ready);
+        let second_frame = dma.writes().len() - 2 * 64;
+        assert!(second_frame > 0 && second_frame < 2 * 64);
+    }
+
+    #[test]
+    fn truncate_keeps_short_strings() {
+        assert_eq!(truncate("Gorillaz".to_string(), 10), "Gorillaz");
+        assert_eq!(truncate("Gorillaz".to_string(), 8), "Gorillaz");
+    }
+
+    #[test]
+    fn truncate_counts_chars_not_bytes() {
+        assert_eq!(truncate("Café del Mar".to_string(), 4), "Café...");
+    }
+
+    #[test]
+    fn format_time_switches_to_hours() {
+        assert_eq!(format_time(Duration::from_millis(7_900)), "0:07");
+        assert_eq!(format_time(Duration::from_secs(227)), "3:47");
+        assert_eq!(format_time(Duration::from_secs(3599)), "59:59");
+        assert_eq!(format_time(Duration::from_secs(3723)), "1:02:03");
+    }
+
+    #[test]
+    fn draw_bar_fills_proportionally() {
+        let mut fb = FrameBuffer::new();
+        draw_bar(&mut fb, "VOLUME", 50.0, 0.0, 100.0, "%");
+
+        // Bar spans x = 4..251, so the filled half ends around x = 127
+        assert_eq!(fb.pixel(4, 40), 15);
+        assert_eq!(fb.pixel(120, 40), 15);
+        assert_eq!(fb.pixel(200, 40), 4);
+    }
+
+    #[test]
+    fn draw_bar_clamps_out_of_range_values() {
+        let mut fb = FrameBuffer::new();
+        draw_bar(&mut fb, "VOLUME", 150.0, 0.0, 100.0, "%");
+        assert_eq!(fb.pixel(249, 40), 15);
+
+        let mut fb = FrameBuffer::new();
+        draw_bar(&mut fb, "VOLUME", -10.0, 0.0, 100.0, "%");
+        assert_eq!(fb.pixel(4, 40), 4);
+    }
+}
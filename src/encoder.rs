@@ -0,0 +1,393 @@
+use embedded_hal::digital::InputPin;
+use crate::button::{Button, GestureTiming};
+use crate::clock::{Duration, Instant};
+use crate::screen::InputAction;
+
+pub const EDGES_PER_DETENT: i32 = 2;
+
+// Detents further apart than this never count towards a sweep
+const SWEEP_TIMEOUT: Duration = Duration::from_millis(250);
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Curve {
+    Linear,
+    // Gentle near the threshold, so moderately quick turns stay fairly precise
+    Quadratic,
+}
+
+// Maps turning speed, in detents per second, to the number of steps each detent is worth.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Acceleration {
+    pub curve: Curve,
+    // At or below this rate every detent is a single step
+    pub min_rate: f32,
+    // At or above this rate every detent is worth `max_step` steps
+    pub max_rate: f32,
+    pub max_step: u32,
+}
+
+impl Acceleration {
+    // One step per detent, however fast the knob turns
+    pub const OFF: Acceleration = Acceleration {
+        curve: Curve::Linear,
+        min_rate: 0.0,
+        max_rate: 1.0,
+        max_step: 1,
+    };
+
+    pub fn steps(&self, rate: f32) -> i32 {
+        let span = self.max_rate - self.min_rate;
+        let t = if span > 0.0 { ((rate - self.min_rate) / span).clamp(0.0, 1.0) } else { 1.0 };
+        let t = match self.curve {
+            Curve::Linear => t,
+            Curve::Quadratic => t * t,
+        };
+
+        1 + (t * self.max_step.saturating_sub(1) as f32 + 0.5) as i32
+    }
+}
+
+impl Default for Acceleration {
+    fn default() -> Self {
+        Acceleration {
+            curve: Curve::Quadratic,
+            min_rate: 5.0,
+            max_rate: 40.0,
+            max_step: 10,
+        }
+    }
+}
+
+// Gray-code transition table: +1 for a clockwise edge, -1 for counter-clockwise,
+// 0 for no movement or an invalid (bounced) transition.
+pub fn decode(last_state: u8, state: u8) -> i32 {
+    match (last_state, state) {
+        (0b00, 0b01) | (0b01, 0b11) | (0b11, 0b10) | (0b10, 0b00) => 1,   // CW
+        (0b00, 0b10) | (0b10, 0b11) | (0b11, 0b01) | (0b01, 0b00) => -1,  // CCW
+        _ => 0,
+    }
+}
+
+// Quadrature encoder with push button. `update` samples the pins and reports detents and
+// button gestures as `InputAction`s; it is cheap enough to run from the pins' edge interrupt,
+// but also needs calling periodically so held and released button timers can expire.
+pub struct Encoder<P>
+where
+    P: InputPin
+{
+    a_pin: P,
+    b_pin: P,
+    button: Button<P>,
+    position: i32,
+    last_state: u8,
+    acceleration: Acceleration,
+    // Time and direction of the previous detent
+    last_detent: Option<(Instant, i32)>,
+}
+
+impl<P> Encoder<P>
+where
+    P: InputPin
+{
+    pub fn new(a: P, b: P, c: P) -> Self {
+        Encoder {
+            a_pin: a,
+            b_pin: b,
+            button: Button::new(c),
+            position: 0,
+            last_state: 0b00,
+            acceleration: Acceleration::default(),
+            last_detent: None,
+        }
+    }
+
+    pub fn with_acceleration(mut self, acceleration: Acceleration) -> Self {
+        self.acceleration = acceleration;
+        self
+    }
+
+    pub fn with_timing(mut self, timing: GestureTiming) -> Self {
+        self.button = self.button.with_timing(timing);
+        self
+    }
+
+    pub fn update<F>(&mut self, now: Instant, mut emit: F)
+    where
+        F: FnMut(InputAction)
+    {
+        self.button.update(now, &mut emit);
+
+        let state = self.read_stable();
+        let delta = decode(self.last_state, state);
+
+        self.position += delta;
+
+        if self.position % EDGES_PER_DETENT == 0 && delta != 0 {
+            let held = self.button.rotated(&mut emit);
+            let steps = delta * self.detent_steps(now, delta);
+            emit(if held { InputAction::PressTurn(steps) } else { InputAction::Turn(steps) });
+        }
+
+        self.last_state = state;
+    }
+
+    // A, B and C, for acknowledging their interrupts
+    pub fn pins_mut(&mut self) -> [&mut P; 3] {
+        [&mut self.a_pin, &mut self.b_pin, self.button.pin_mut()]
+    }
+
+    // Speed is taken from the gap since the previous detent; a pause or a change of
+    // direction starts over at single steps.
+    fn detent_steps(&mut self, now: Instant, direction: i32) -> i32 {
+        let previous = self.last_detent.replace((now, direction));
+
+        match previous {
+            Some((at, dir)) if dir == direction && now.duration_since(at) < SWEEP_TIMEOUT => {
+                let gap = now.duration_since(at).as_micros().max(1) as f32;
+                self.acceleration.steps(1_000_000.0 / gap)
+            }
+            _ => 1,
+        }
+    }
+
+    fn read_ab(&mut self) -> u8 {
+        let a = self.a_pin.is_high().unwrap_or(false);
+        let b = self.b_pin.is_high().unwrap_or(false);
+        (a as u8) << 1 | b as u8
+    }
+
+    fn read_stable(&mut self) -> u8 {
+        let mut last = self.read_ab();
+        for _ in 0..5 {
+            let current = self.read_ab();
+            if current == last {
+                return current;
+            }
+            last = current;
+        }
+        last
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec::Vec;
+    use crate::clock::{Clock, Duration, MockClock};
+    use crate::mock::MockPin;
+    use crate::queue::EventQueue;
+
+    struct Harness {
+        encoder: Encoder<MockPin>,
+        a: MockPin,
+        b: MockPin,
+        c: MockPin,
+        clock: MockClock,
+        events: Vec<InputAction>,
+    }
+
+    impl Harness {
+        fn new() -> Self {
+            let (a, b, c) = (MockPin::new(false), MockPin::new(false), MockPin::new(true));
+            let encoder = Encoder::new(a.clone(), b.clone(), c.clone());
+
+            let mut h = Harness { encoder, a, b, c, clock: MockClock::new(), events: Vec::new() };
+            h.update();
+            h
+        }
+
+        fn update(&mut self) {
+            self.encoder.update(self.clock.now(), |event| self.events.push(event));
+        }
+
+        // Moves to the next Gray code state `ms` after the previous one
+        fn step_after(&mut self, ms: u64, state: u8) {
+            self.a.set(state & 0b10 != 0);
+            self.b.set(state & 0b01 != 0);
+            self.clock.advance(Duration::from_millis(ms));
+            self.update();
+        }
+
+        // A slow, deliberate turn
+        fn step(&mut self, state: u8) {
+            self.step_after(50, state);
+        }
+
+        // `detents` clockwise detents at a steady rate
+        fn spin(&mut self, detents: usize, ms_per_detent: u64) {
+            for state in [0b01, 0b11, 0b10, 0b00].iter().cycle().take(2 * detents) {
+                self.step_after(ms_per_detent / 2, *state);
+            }
+        }
+
+        fn run_for(&mut self, ms: u64) {
+            for _ in 0..ms {
+                self.clock.advance(Duration::from_millis(1));
+                self.update();
+            }
+        }
+
+        fn detent_cw(&mut self) {
+            for state in [0b01, 0b11] {
+                self.step(state);
+            }
+        }
+    }
+
+    #[test]
+    fn decode_table_is_antisymmetric() {
+        for last in 0..4 {
+            for state in 0..4 {
+                assert_eq!(decode(last, state), -decode(state, last));
+            }
+        }
+        assert_eq!(decode(0b00, 0b11), 0);
+    }
+
+    #[test]
+    fn reports_one_event_per_detent() {
+        let mut h = Harness::new();
+
+        for state in [0b01, 0b11, 0b10, 0b00] {
+            h.step(state);
+        }
+        assert_eq!(h.events, [InputAction::Turn(1), InputAction::Turn(1)]);
+
+        h.events.clear();
+        for state in [0b10, 0b11, 0b01, 0b00] {
+            h.step(state);
+        }
+        assert_eq!(h.events, [InputAction::Turn(-1), InputAction::Turn(-1)]);
+    }
+
+    #[test]
+    fn button_reports_short_press_after_double_click_window() {
+        let mut h = Harness::new();
+
+        h.c.set(false);
+        h.run_for(100);
+        h.c.set(true);
+        h.run_for(100);
+        assert!(h.events.is_empty());
+
+        h.run_for(300);
+        assert_eq!(h.events, [InputAction::ShortPress]);
+    }
+
+    #[test]
+    fn rotating_while_held_is_press_and_rotate() {
+        let mut h = Harness::new();
+
+        h.c.set(false);
+        h.run_for(50);
+        h.detent_cw();
+        for state in [0b01, 0b00] {
+            h.step(state);
+        }
+        h.c.set(true);
+        h.run_for(1000);
+
+        assert_eq!(h.events, [InputAction::PressTurn(1), InputAction::PressTurn(-1)]);
+    }
+
+    #[test]
+    fn turning_right_after_a_click_settles_the_click_first() {
+        let mut h = Harness::new();
+
+        h.c.set(false);
+        h.run_for(50);
+        h.c.set(true);
+        h.run_for(50);
+        h.detent_cw();
+
+        assert_eq!(h.events, [InputAction::ShortPress, InputAction::Turn(1)]);
+    }
+
+    #[test]
+    fn acceleration_curve_is_capped() {
+        let linear = Acceleration { curve: Curve::Linear, min_rate: 10.0, max_rate: 30.0, max_step: 5 };
+        assert_eq!(linear.steps(0.0), 1);
+        assert_eq!(linear.steps(10.0), 1);
+        assert_eq!(linear.steps(20.0), 3);
+        assert_eq!(linear.steps(30.0), 5);
+        assert_eq!(linear.steps(500.0), 5);
+
+        let quadratic = Acceleration { curve: Curve::Quadratic, ..linear };
+        assert_eq!(quadratic.steps(20.0), 2);
+        assert_eq!(quadratic.steps(30.0), 5);
+
+        assert_eq!(Acceleration::OFF.steps(500.0), 1);
+    }
+
+    #[test]
+    fn slow_turns_stay_single_steps() {
+        let mut h = Harness::new();
+        h.spin(8, 250);
+        assert!(h.events.iter().all(|e| *e == InputAction::Turn(1)));
+        assert_eq!(h.events.len(), 8);
+    }
+
+    #[test]
+    fn fast_sweep_accelerates_up_to_the_cap() {
+        let mut h = Harness::new();
+        h.spin(10, 10);
+
+        let steps: Vec<i32> = h.events.iter()
+            .map(|e| match e { InputAction::Turn(n) => *n, _ => 0 })
+            .collect();
+
+        // The first detent of a sweep has nothing to measure against
+        assert_eq!(steps[0], 1);
+        assert!(steps[1..].iter().all(|n| *n == 10));
+    }
+
+    #[test]
+    fn reversing_or_pausing_restarts_at_one_step() {
+        let mut h = Harness::new();
+        h.spin(3, 40);
+        assert_eq!(h.events.last(), Some(&InputAction::Turn(4)));
+
+        // Back the other way, faster still
+        for state in [0b01, 0b00] {
+            h.step_after(10, state);
+        }
+        assert_eq!(h.events.last(), Some(&InputAction::Turn(-1)));
+
+        h.run_for(500);
+        h.events.clear();
+        for state in [0b10, 0b11] {
+            h.step_after(10, state);
+        }
+        assert_eq!(h.events, [InputAction::Turn(-1)]);
+    }
+
+    #[test]
+    fn acceleration_can_be_disabled() {
+        let mut h = Harness::new();
+        h.encoder = Encoder::new(h.a.clone(), h.b.clone(), h.c.clone())
+            .with_acceleration(Acceleration::OFF);
+        h.update();
+        h.spin(10, 10);
+        assert!(h.events.iter().all(|e| *e == InputAction::Turn(1)));
+    }
+
+    #[test]
+    fn fast_spin_survives_a_slow_consumer() {
+        // Every edge is handled as it happens, the way the pin interrupt does; the main
+        // loop only gets to drain the queue once the whole spin is over
+        static EVENTS: EventQueue<InputAction, 32> = EventQueue::new();
+        let (mut tx, mut rx) = EVENTS.split().unwrap();
+        let mut h = Harness::new();
+
+        for _ in 0..10 {
+            for state in [0b01, 0b11, 0b10, 0b00] {
+                h.a.set(state & 0b10 != 0);
+                h.b.set(state & 0b01 != 0);
+                h.encoder.update(h.clock.now(), |event| { tx.push(event).ok(); });
+            }
+        }
+
+        let drained = core::iter::from_fn(|| rx.pop()).count();
+        assert_eq!(drained, 20);
+    }
+}
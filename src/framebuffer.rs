@@ -0,0 +1,160 @@
+use embedded_graphics::draw_target::DrawTarget;
+use embedded_graphics::geometry::Size;
+use embedded_graphics::Pixel;
+use embedded_graphics::pixelcolor::{Gray4, IntoStorage};
+use embedded_graphics::prelude::OriginDimensions;
+
+pub const WIDTH: usize = 256;
+pub const HEIGHT: usize = 64;
+pub const ROW_BYTES: usize = WIDTH / 2;
+pub const BUFFER_SIZE: usize = ROW_BYTES * HEIGHT;
+
+// Bytes `start..end` of buffer row `row` (bottom-up storage order) differ from what was sent.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct DirtySpan {
+    pub row: usize,
+    pub start: usize,
+    pub end: usize,
+}
+
+// 4-bit packed framebuffer in the layout the SH1122 expects: two pixels per byte,
+// even columns in the high nibble, rows stored bottom-up.
+#[derive(Clone)]
+pub struct FrameBuffer {
+    buffer: [u8; BUFFER_SIZE],
+}
+
+impl FrameBuffer {
+    pub fn new() -> Self {
+        FrameBuffer {
+            buffer: [0; BUFFER_SIZE],
+        }
+    }
+
+    pub fn set_pixel(&mut self, x: usize, y: usize, value: u8) {
+        if x >= WIDTH || y >= HEIGHT { return; }
+
+        let value = if value > 15 { 15 } else { value };
+
+        let y = HEIGHT - 1 - y;
+        let index = (y * WIDTH + x) / 2;
+        let is_high_nibble = x.is_multiple_of(2);
+
+        if is_high_nibble {
+            self.buffer[index] = (self.buffer[index] & 0x0F) | (value << 4);
+        } else {
+            self.buffer[index] = (self.buffer[index] & 0xF0) | value;
+        }
+    }
+
+    pub fn pixel(&self, x: usize, y: usize) -> u8 {
+        if x >= WIDTH || y >= HEIGHT { return 0; }
+
+        let y = HEIGHT - 1 - y;
+        let byte = self.buffer[(y * WIDTH + x) / 2];
+
+        if x.is_multiple_of(2) { byte >> 4 } else { byte & 0x0F }
+    }
+
+    pub fn clear(&mut self) {
+        for b in self.buffer.iter_mut() {
+            *b = 0;
+        }
+    }
+
+    pub fn as_bytes(&self) -> &[u8; BUFFER_SIZE] {
+        &self.buffer
+    }
+
+    pub fn row(&self, row: usize) -> &[u8] {
+        &self.buffer[row * ROW_BYTES..(row + 1) * ROW_BYTES]
+    }
+
+    // Per-row byte ranges where this buffer differs from `sent`, the last frame the panel received.
+    pub fn dirty_spans<'a>(&'a self, sent: &'a FrameBuffer) -> impl Iterator<Item = DirtySpan> + 'a {
+        (0..HEIGHT).filter_map(move |row| {
+            let new = self.row(row);
+            let old = sent.row(row);
+
+            let start = new.iter().zip(old).position(|(a, b)| a != b)?;
+            let end = ROW_BYTES - new.iter().zip(old).rev().position(|(a, b)| a != b)?;
+
+            Some(DirtySpan { row, start, end })
+        })
+    }
+}
+
+impl Default for FrameBuffer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DrawTarget for FrameBuffer {
+    type Color = Gray4;
+    type Error = core::convert::Infallible;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item=Pixel<Self::Color>>
+    {
+        for Pixel(coord, color) in pixels {
+            if let (x @ 0..=255, y @ 0..=63) = coord.into() {
+                self.set_pixel(x as usize, y as usize, color.into_storage());
+            }
+        }
+        Ok(())
+    }
+}
+
+impl OriginDimensions for FrameBuffer {
+    fn size(&self) -> Size {
+        Size::new(WIDTH as u32, HEIGHT as u32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec::Vec;
+
+    #[test]
+    fn packs_two_pixels_per_byte_bottom_up() {
+        let mut fb = FrameBuffer::new();
+        fb.set_pixel(0, 0, 0xA);
+        fb.set_pixel(1, 0, 0x5);
+
+        let last_row = (HEIGHT - 1) * WIDTH / 2;
+        assert_eq!(fb.as_bytes()[last_row], 0xA5);
+        assert_eq!(fb.pixel(0, 0), 0xA);
+        assert_eq!(fb.pixel(1, 0), 0x5);
+    }
+
+    #[test]
+    fn dirty_spans_cover_changed_bytes_per_row() {
+        let sent = FrameBuffer::new();
+        let mut fb = sent.clone();
+        assert_eq!(fb.dirty_spans(&sent).count(), 0);
+
+        fb.set_pixel(10, 0, 1);
+        fb.set_pixel(41, 0, 1);
+        fb.set_pixel(255, 5, 1);
+
+        let spans: Vec<DirtySpan> = fb.dirty_spans(&sent).collect();
+        assert_eq!(spans, [
+            DirtySpan { row: 58, start: 127, end: 128 },
+            DirtySpan { row: 63, start: 5, end: 21 },
+        ]);
+    }
+
+    #[test]
+    fn clamps_and_ignores_out_of_range() {
+        let mut fb = FrameBuffer::new();
+        fb.set_pixel(3, 63, 200);
+        fb.set_pixel(256, 0, 15);
+        fb.set_pixel(0, 64, 15);
+
+        assert_eq!(fb.pixel(3, 63), 15);
+        assert_eq!(fb.as_bytes().iter().filter(|b| **b != 0).count(), 1);
+    }
+}
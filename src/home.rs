@@ -0,0 +1,503 @@
+use alloc::format;
+use embedded_graphics::Drawable;
+use embedded_graphics::geometry::{Point, Size};
+use embedded_graphics::mono_font::ascii::{FONT_6X10, FONT_7X13, FONT_7X13_BOLD};
+use embedded_graphics::mono_font::MonoTextStyle;
+use embedded_graphics::pixelcolor::Gray4;
+use embedded_graphics::prelude::{DrawTarget, Primitive};
+use embedded_graphics::primitives::{PrimitiveStyle, Rectangle};
+use embedded_graphics::text::{Alignment, Text};
+use crate::clock::{Duration, Instant};
+use crate::display;
+use crate::marquee::Marquee;
+use crate::screen::{
+    Bindings, InputEvent, Navigation, PRIMARY_ENCODER, SECONDARY_ENCODER, Screen, Trigger,
+    WHEEL_MODE, WHEEL_NEXT, WHEEL_PREV, WHEEL_VOLUME_DOWN, WHEEL_VOLUME_UP,
+};
+use crate::settings::settings_menu;
+use crate::source::{now_playing, source_menu};
+use crate::state::{ActiveScreen, State, TrackSkip};
+
+const VOLUME_OVERLAY: Duration = Duration::from_millis(1000);
+const VOLUME_STEP: u32 = 2;
+const VOLUME_MAX: u32 = 100;
+
+// Progress bar between the elapsed and remaining times
+const PROGRESS_LEFT: i32 = 52;
+const PROGRESS_RIGHT: i32 = display::WIDTH - 52;
+const PROGRESS_TOP: i32 = 55;
+const PROGRESS_HEIGHT: u32 = 4;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum HomeAction {
+    Volume,
+    VolumeUp,
+    VolumeDown,
+    NextTrack,
+    PreviousTrack,
+    OpenSettings,
+    OpenSources,
+}
+
+#[derive(Clone)]
+pub struct HomeScreen {
+    volume_shown_at: Option<Instant>,
+    title: Marquee,
+    artist: Marquee,
+    // Time of the last update, which the track position is drawn for
+    now: Instant,
+    // Elapsed seconds and bar length last drawn
+    progress: (u64, u32),
+    bindings: Bindings<HomeAction>,
+}
+
+impl HomeScreen {
+    pub fn new() -> Self {
+        HomeScreen {
+            volume_shown_at: None,
+            title: Marquee::new(&FONT_7X13_BOLD, display::WIDTH as u32 + 1),
+            artist: Marquee::new(&FONT_7X13, display::WIDTH as u32 + 1),
+            now: Instant::default(),
+            progress: (0, 0),
+            bindings: Bindings::new()
+                .bind(PRIMARY_ENCODER, Trigger::Turn, HomeAction::Volume)
+                .bind(PRIMARY_ENCODER, Trigger::ShortPress, HomeAction::OpenSettings)
+                .bind(SECONDARY_ENCODER, Trigger::ShortPress, HomeAction::OpenSources)
+                .bind(WHEEL_MODE, Trigger::ShortPress, HomeAction::OpenSources)
+                .bind(WHEEL_VOLUME_UP, Trigger::ShortPress, HomeAction::VolumeUp)
+                .bind(WHEEL_VOLUME_UP, Trigger::LongPress, HomeAction::VolumeUp)
+                .bind(WHEEL_VOLUME_UP, Trigger::Repeat, HomeAction::VolumeUp)
+                .bind(WHEEL_VOLUME_DOWN, Trigger::ShortPress, HomeAction::VolumeDown)
+                .bind(WHEEL_VOLUME_DOWN, Trigger::LongPress, HomeAction::VolumeDown)
+                .bind(WHEEL_VOLUME_DOWN, Trigger::Repeat, HomeAction::VolumeDown)
+                .bind(WHEEL_NEXT, Trigger::ShortPress, HomeAction::NextTrack)
+                .bind(WHEEL_PREV, Trigger::ShortPress, HomeAction::PreviousTrack),
+        }
+    }
+
+    pub fn with_bindings(mut self, bindings: Bindings<HomeAction>) -> Self {
+        self.bindings = bindings;
+        self
+    }
+}
+
+impl Default for HomeScreen {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Screen for HomeScreen {
+    fn draw<D>(&self, state: &State, target: &mut D) where D: DrawTarget<Color = Gray4> {
+        if self.volume_shown_at.is_some() {
+            display::draw_bar(target, "VOLUME", state.volume() as f32, 0_f32, 100_f32, "%");
+            return;
+        }
+
+        // Voltage
+        Text::with_alignment(
+            format!("{:.1} V", state.voltage()).as_str(),
+            Point::new(0, 9),
+            MonoTextStyle::new(&FONT_6X10, Gray4::new(15)),
+            Alignment::Left
+        ).draw(target).ok();
+
+        // Power
+        Text::with_alignment(
+            format!("{} W", (state.voltage() * state.current()) as i32).as_str(),
+            Point::new(48, 9),
+            MonoTextStyle::new(&FONT_6X10, Gray4::new(15)),
+            Alignment::Left
+        ).draw(target).ok();
+
+        // Source
+        Text::with_alignment(
+            state.audio_source().label(),
+            Point::new(140, 9),
+            MonoTextStyle::new(&FONT_6X10, Gray4::new(15)),
+            Alignment::Center
+        ).draw(target).ok();
+
+        // Antenna
+        Text::with_alignment(
+            if state.antenna_up() { "UP" } else { "DOWN" },
+            Point::new(crate::display::WIDTH - 36, 9),
+            MonoTextStyle::new(&FONT_6X10, Gray4::new(15)),
+            Alignment::Right
+        ).draw(target).ok();
+
+        // Power State
+        Text::with_alignment(
+            match state.power_setting() {
+                crate::state::PowerSetting::ON => "ON",
+                crate::state::PowerSetting::AUTO => "AUTO",
+                crate::state::PowerSetting::OFF => "OFF",
+            },
+            Point::new(display::WIDTH, 9),
+            MonoTextStyle::new(&FONT_6X10, Gray4::new(15)),
+            Alignment::Right
+        ).draw(target).ok();
+
+        // Track title and artist, or whatever the source has instead
+        let (title, artist) = now_playing(state);
+        self.title.draw(target, &title, Point::new(0, 32), Gray4::new(15));
+        self.artist.draw(target, &artist, Point::new(0, 48), Gray4::new(15));
+
+        // Track Progress, for sources with a track length
+        let duration = state.track_duration();
+        if duration.is_zero() || !state.audio_source().has_tracks() {
+            return;
+        }
+
+        let position = state.track_position(self.now);
+        let color = if state.is_playing() { Gray4::new(15) } else { Gray4::new(8) };
+
+        Text::with_alignment(
+            display::format_time(position).as_str(),
+            Point::new(0, display::HEIGHT - 1),
+            MonoTextStyle::new(&FONT_6X10, color),
+            Alignment::Left
+        ).draw(target).ok();
+
+        Text::with_alignment(
+            format!("-{}", display::format_time(duration.saturating_sub(position))).as_str(),
+            Point::new(display::WIDTH, display::HEIGHT - 1),
+            MonoTextStyle::new(&FONT_6X10, color),
+            Alignment::Right
+        ).draw(target).ok();
+
+        Rectangle::new(Point::new(PROGRESS_LEFT, PROGRESS_TOP), Size::new((PROGRESS_RIGHT - PROGRESS_LEFT) as u32, PROGRESS_HEIGHT))
+            .into_styled(PrimitiveStyle::with_fill(Gray4::new(3)))
+            .draw(target).ok();
+
+        Rectangle::new(Point::new(PROGRESS_LEFT, PROGRESS_TOP), Size::new(progress_width(position, duration), PROGRESS_HEIGHT))
+            .into_styled(PrimitiveStyle::with_fill(color))
+            .draw(target).ok();
+    }
+
+    fn update(&mut self, prev_state: &State, state: &State, now: Instant) -> bool {
+        self.now = now;
+
+        // The interpolated position only needs a redraw when the time or bar visibly moves
+        let position = state.track_position(now);
+        let progress = (position.as_secs(), progress_width(position, state.track_duration()));
+        let progressed = core::mem::replace(&mut self.progress, progress) != progress
+            && self.volume_shown_at.is_none();
+
+        // Long titles keep scrolling underneath the volume overlay but only redraw when visible
+        let (title, artist) = now_playing(state);
+        let title_moved = self.title.update(&title, now);
+        let artist_moved = self.artist.update(&artist, now);
+        let scrolled = (title_moved || artist_moved) && self.volume_shown_at.is_none();
+
+        if state.volume() != prev_state.volume() {
+            self.volume_shown_at = Some(now);
+            return true;
+        }
+
+        match self.volume_shown_at {
+            Some(shown_at) if now.duration_since(shown_at) >= VOLUME_OVERLAY => {
+                self.volume_shown_at = None;
+                true
+            }
+            _ => scrolled || progressed,
+        }
+    }
+
+    fn is_animating(&self) -> bool {
+        self.volume_shown_at.is_none() && (self.title.is_scrolling() || self.artist.is_scrolling())
+    }
+
+    // Turning the knob sets the volume; `update` notices the change and shows the overlay.
+    // Pressing it opens the settings, and the second knob or the wheel's mode key the sources.
+    // The wheel's next and previous keys are passed on to the host.
+    fn handle_event(&mut self, state: &State, input: InputEvent) -> Navigation {
+        let steps = match self.bindings.lookup(&input) {
+            Some(HomeAction::Volume) => input.action.steps(),
+            Some(HomeAction::VolumeUp) => 1,
+            Some(HomeAction::VolumeDown) => -1,
+            Some(HomeAction::NextTrack) => return skip(state, TrackSkip::Next),
+            Some(HomeAction::PreviousTrack) => return skip(state, TrackSkip::Previous),
+            Some(HomeAction::OpenSettings) => return Navigation::Push(ActiveScreen::Menu(settings_menu())),
+            Some(HomeAction::OpenSources) => return Navigation::Push(ActiveScreen::Menu(source_menu(state))),
+            None => return Navigation::Stay,
+        };
+
+        let volume = state.volume() as i32 + steps * VOLUME_STEP as i32;
+        state.set_volume(volume.clamp(0, VOLUME_MAX as i32) as u32);
+        Navigation::Stay
+    }
+}
+
+fn skip(state: &State, skip: TrackSkip) -> Navigation {
+    state.skip_track(skip);
+    Navigation::Stay
+}
+
+// Filled length of the progress bar
+fn progress_width(position: Duration, duration: Duration) -> u32 {
+    if duration.is_zero() {
+        return 0;
+    }
+
+    let width = (PROGRESS_RIGHT - PROGRESS_LEFT) as u128;
+    (width * position.as_millis() / duration.as_millis()).min(width) as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::{Clock, MockClock};
+    use crate::framebuffer::FrameBuffer;
+    use crate::screen::InputAction;
+    use crate::state::AudioSource;
+
+    // Sources with tracks show the title, artist and progress
+    fn usb_state() -> State {
+        let state = State::new();
+        state.set_audio_source(AudioSource::USB);
+        state
+    }
+
+    fn turn(steps: i32) -> InputEvent {
+        InputEvent::new(PRIMARY_ENCODER, InputAction::Turn(steps))
+    }
+
+    #[test]
+    fn volume_change_requests_redraw() {
+        let mut screen = HomeScreen::new();
+        let prev = State::new();
+        let state = prev.clone();
+
+        assert!(!screen.update(&prev, &state, Instant::default()));
+
+        state.set_volume(60);
+        assert!(screen.update(&prev, &state, Instant::default()));
+    }
+
+    #[test]
+    fn volume_overlay_expires_after_a_second() {
+        let clock = MockClock::new();
+        // Well past where the old 32-bit cycle counter wrapped
+        clock.set(Instant::from_millis(40_000));
+
+        let mut screen = HomeScreen::new();
+        let prev = State::new();
+        let state = prev.clone();
+        state.set_volume(70);
+        assert!(screen.update(&prev, &state, clock.now()));
+
+        let prev = state.clone();
+        clock.advance(Duration::from_millis(900));
+        assert!(!screen.update(&prev, &state, clock.now()));
+        assert!(screen.volume_shown_at.is_some());
+
+        clock.advance(Duration::from_millis(100));
+        assert!(screen.update(&prev, &state, clock.now()));
+        assert!(screen.volume_shown_at.is_none());
+        assert!(!screen.update(&prev, &state, clock.now()));
+    }
+
+    #[test]
+    fn rotation_steps_volume_within_range() {
+        let mut screen = HomeScreen::new();
+        let state = State::new();
+
+        screen.handle_event(&state, turn(1));
+        assert_eq!(state.volume(), 52);
+        screen.handle_event(&state, turn(-1));
+        screen.handle_event(&state, turn(-1));
+        assert_eq!(state.volume(), 48);
+
+        // An accelerated sweep moves several steps at once
+        screen.handle_event(&state, turn(10));
+        assert_eq!(state.volume(), 68);
+
+        state.set_volume(99);
+        screen.handle_event(&state, turn(1));
+        assert_eq!(state.volume(), 100);
+
+        state.set_volume(5);
+        screen.handle_event(&state, turn(-8));
+        assert_eq!(state.volume(), 0);
+
+        screen.handle_event(&state, InputEvent::new(PRIMARY_ENCODER, InputAction::LongPress));
+        assert_eq!(state.volume(), 0);
+    }
+
+    #[test]
+    fn pressing_the_knob_opens_settings() {
+        let mut screen = HomeScreen::new();
+        let state = State::new();
+
+        let navigation = screen.handle_event(&state, InputEvent::new(PRIMARY_ENCODER, InputAction::ShortPress));
+        assert!(matches!(navigation, Navigation::Push(ActiveScreen::Menu(_))));
+        assert_eq!(state.volume(), 50);
+    }
+
+    #[test]
+    fn volume_follows_its_binding() {
+        let state = State::new();
+        let secondary = InputEvent::new(SECONDARY_ENCODER, InputAction::Turn(1));
+
+        HomeScreen::new().handle_event(&state, secondary);
+        assert_eq!(state.volume(), 50);
+
+        let mut rebound = HomeScreen::new()
+            .with_bindings(Bindings::new().bind(SECONDARY_ENCODER, Trigger::Turn, HomeAction::Volume));
+        rebound.handle_event(&state, secondary);
+        rebound.handle_event(&state, turn(1));
+        assert_eq!(state.volume(), 52);
+    }
+
+    #[test]
+    fn wheel_keys_step_volume() {
+        let mut screen = HomeScreen::new();
+        let state = State::new();
+
+        screen.handle_event(&state, InputEvent::new(WHEEL_VOLUME_UP, InputAction::ShortPress));
+        screen.handle_event(&state, InputEvent::new(WHEEL_VOLUME_UP, InputAction::ShortPress));
+        assert_eq!(state.volume(), 54);
+        screen.handle_event(&state, InputEvent::new(WHEEL_VOLUME_DOWN, InputAction::ShortPress));
+        assert_eq!(state.volume(), 52);
+
+        // Holding a key steps on the long press and then on every repeat
+        screen.handle_event(&state, InputEvent::new(WHEEL_VOLUME_DOWN, InputAction::LongPress));
+        for _ in 0..3 {
+            screen.handle_event(&state, InputEvent::new(WHEEL_VOLUME_DOWN, InputAction::Repeat));
+        }
+        assert_eq!(state.volume(), 44);
+    }
+
+    #[test]
+    fn wheel_track_keys_ask_the_host_to_skip() {
+        let mut screen = HomeScreen::new();
+        let state = State::new();
+
+        screen.handle_event(&state, InputEvent::new(WHEEL_NEXT, InputAction::ShortPress));
+        assert_eq!(state.take_track_skip(), Some(TrackSkip::Next));
+        screen.handle_event(&state, InputEvent::new(WHEEL_PREV, InputAction::ShortPress));
+        assert_eq!(state.take_track_skip(), Some(TrackSkip::Previous));
+
+        // Holding them does nothing
+        screen.handle_event(&state, InputEvent::new(WHEEL_NEXT, InputAction::LongPress));
+        assert_eq!(state.take_track_skip(), None);
+    }
+
+    #[test]
+    fn long_titles_scroll_and_short_ones_stay_put() {
+        let clock = MockClock::new();
+        let mut screen = HomeScreen::new();
+        let state = usb_state();
+        state.set_track_artist("Gorillaz");
+
+        // Counts the updates asking for a redraw over the next `ms`
+        let run = |screen: &mut HomeScreen, ms: u64| {
+            (0..ms / 10).filter(|_| {
+                clock.advance(Duration::from_millis(10));
+                screen.update(&state, &state, clock.now())
+            }).count()
+        };
+
+        run(&mut screen, 10);
+        assert_eq!(run(&mut screen, 1400), 0);
+        assert!(!screen.is_animating());
+        assert!(run(&mut screen, 1000) > 20);
+        assert!(screen.is_animating());
+
+        state.set_track_title("Stylo");
+        assert_eq!(run(&mut screen, 10_000), 0);
+        assert!(!screen.is_animating());
+    }
+
+    #[test]
+    fn playing_track_redraws_as_the_bar_moves() {
+        let clock = MockClock::new();
+        let mut screen = HomeScreen::new();
+        let state = usb_state();
+        state.set_track_title("Stylo");
+
+        // 151 px of bar over 151 s: a redraw a second, for the time and bar together
+        state.set_playback(Duration::ZERO, Duration::from_secs(151), true, clock.now());
+        screen.update(&state, &state, clock.now());
+        let redraws = (0..1000).filter(|_| {
+            clock.advance(Duration::from_millis(10));
+            screen.update(&state, &state, clock.now())
+        }).count();
+        assert_eq!(redraws, 10);
+
+        // Paused, nothing moves
+        state.set_playback(Duration::from_secs(10), Duration::from_secs(151), false, clock.now());
+        screen.update(&state, &state, clock.now());
+        clock.advance(Duration::from_secs(5));
+        assert!(!screen.update(&state, &state, clock.now()));
+    }
+
+    #[test]
+    fn tuner_shows_station_without_progress() {
+        let mut screen = HomeScreen::new();
+        let state = State::new();
+        state.set_tuner(92_600, "Radio 2");
+        screen.update(&state, &state, Instant::default());
+
+        let mut fb = FrameBuffer::new();
+        screen.draw(&state, &mut fb);
+        assert!((0..70).any(|x| fb.pixel(x, 28) != 0));
+        assert!((0..display::WIDTH as usize).all(|x| fb.pixel(x, PROGRESS_TOP as usize + 1) == 0));
+    }
+
+    #[test]
+    fn second_knob_and_mode_key_open_sources() {
+        let state = State::new();
+        let mut screen = HomeScreen::new();
+
+        for source in [SECONDARY_ENCODER, WHEEL_MODE] {
+            let navigation = screen.handle_event(&state, InputEvent::new(source, InputAction::ShortPress));
+            assert!(matches!(navigation, Navigation::Push(ActiveScreen::Menu(_))));
+        }
+        assert!(matches!(
+            screen.handle_event(&state, InputEvent::new(SECONDARY_ENCODER, InputAction::LongPress)),
+            Navigation::Stay
+        ));
+    }
+
+    #[test]
+    fn draws_progress_and_times() {
+        let clock = MockClock::new();
+        clock.set(Instant::from_millis(1_000));
+        let mut screen = HomeScreen::new();
+        let state = usb_state();
+        state.set_playback(Duration::from_secs(50), Duration::from_secs(200), true, Instant::default());
+        screen.update(&state, &state, clock.now());
+
+        let mut fb = FrameBuffer::new();
+        screen.draw(&state, &mut fb);
+
+        // 51 s of 200: about a quarter of the bar is filled
+        let y = PROGRESS_TOP as usize + 1;
+        assert_eq!(fb.pixel(PROGRESS_LEFT as usize + 30, y), 15);
+        assert_eq!(fb.pixel(PROGRESS_LEFT as usize + 40, y), 3);
+        assert_eq!(fb.pixel(PROGRESS_RIGHT as usize - 1, y), 3);
+        assert!((0..30).any(|x| fb.pixel(x, 60) != 0));
+        assert!((220..256).any(|x| fb.pixel(x, 60) != 0));
+
+        // Without a track length there is no bar
+        state.set_playback(Duration::ZERO, Duration::ZERO, true, Instant::default());
+        let mut fb = FrameBuffer::new();
+        screen.draw(&state, &mut fb);
+        assert!((PROGRESS_LEFT..PROGRESS_RIGHT).all(|x| fb.pixel(x as usize, y) == 0));
+    }
+
+    #[test]
+    fn draws_volume_bar_while_timer_runs() {
+        let mut screen = HomeScreen::new();
+        let prev = State::new();
+        let state = prev.clone();
+        state.set_volume(100);
+        screen.update(&prev, &state, Instant::default());
+
+        let mut fb = FrameBuffer::new();
+        screen.draw(&state, &mut fb);
+        assert_eq!(fb.pixel(128, 40), 15);
+    }
+}
@@ -0,0 +1,172 @@
+use alloc::vec::Vec;
+use embedded_hal::digital::InputPin;
+use crate::button::Button;
+use crate::clock::Instant;
+use crate::encoder::Encoder;
+use crate::screen::{InputEvent, SourceId};
+
+// Owns every knob and button on the dashboard and tags what they report with the
+// source id they were added under.
+pub struct InputManager<P>
+where
+    P: InputPin
+{
+    encoders: Vec<(SourceId, Encoder<P>)>,
+    buttons: Vec<(SourceId, Button<P>)>,
+}
+
+impl<P> InputManager<P>
+where
+    P: InputPin
+{
+    pub fn new() -> Self {
+        InputManager {
+            encoders: Vec::new(),
+            buttons: Vec::new(),
+        }
+    }
+
+    pub fn with_encoder(mut self, source: SourceId, encoder: Encoder<P>) -> Self {
+        debug_assert!(!self.has_source(source), "input source {} added twice", source);
+        self.encoders.push((source, encoder));
+        self
+    }
+
+    pub fn with_button(mut self, source: SourceId, button: Button<P>) -> Self {
+        debug_assert!(!self.has_source(source), "input source {} added twice", source);
+        self.buttons.push((source, button));
+        self
+    }
+
+    // Samples every source. Like `Encoder::update`, call it from the pin interrupt
+    // and periodically from the main loop.
+    pub fn update<F>(&mut self, now: Instant, mut emit: F)
+    where
+        F: FnMut(InputEvent)
+    {
+        for (source, encoder) in &mut self.encoders {
+            encoder.update(now, |action| emit(InputEvent::new(*source, action)));
+        }
+
+        for (source, button) in &mut self.buttons {
+            button.update(now, |action| emit(InputEvent::new(*source, action)));
+        }
+    }
+
+    // Every pin, for acknowledging their interrupts
+    pub fn pins_mut(&mut self) -> impl Iterator<Item = &mut P> {
+        let encoder_pins = self.encoders.iter_mut().flat_map(|(_, encoder)| encoder.pins_mut());
+        let button_pins = self.buttons.iter_mut().map(|(_, button)| button.pin_mut());
+        encoder_pins.chain(button_pins)
+    }
+
+    fn has_source(&self, source: SourceId) -> bool {
+        self.encoders.iter().any(|(s, _)| *s == source) || self.buttons.iter().any(|(s, _)| *s == source)
+    }
+}
+
+impl<P> Default for InputManager<P>
+where
+    P: InputPin
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::{Clock, Duration, MockClock};
+    use crate::mock::MockPin;
+    use crate::screen::InputAction;
+
+    struct Harness {
+        manager: InputManager<MockPin>,
+        // A and B of each encoder, then the buttons
+        knobs: [(MockPin, MockPin); 2],
+        buttons: [MockPin; 2],
+        clock: MockClock,
+        events: Vec<InputEvent>,
+    }
+
+    impl Harness {
+        fn new() -> Self {
+            let pin = |high| MockPin::new(high);
+            let knobs = [(pin(false), pin(false)), (pin(false), pin(false))];
+            let buttons = [pin(true), pin(true)];
+
+            let manager = InputManager::new()
+                .with_encoder(0, Encoder::new(knobs[0].0.clone(), knobs[0].1.clone(), pin(true)))
+                .with_encoder(1, Encoder::new(knobs[1].0.clone(), knobs[1].1.clone(), pin(true)))
+                .with_button(7, Button::new(buttons[0].clone()))
+                .with_button(8, Button::new(buttons[1].clone()));
+
+            let mut h = Harness { manager, knobs, buttons, clock: MockClock::new(), events: Vec::new() };
+            h.run_for(1);
+            h
+        }
+
+        fn run_for(&mut self, ms: u64) {
+            for _ in 0..ms {
+                self.manager.update(self.clock.now(), |event| self.events.push(event));
+                self.clock.advance(Duration::from_millis(1));
+            }
+        }
+
+        fn detent_cw(&mut self, knob: usize) {
+            for state in [0b01, 0b11] {
+                self.knobs[knob].0.set(state & 0b10 != 0);
+                self.knobs[knob].1.set(state & 0b01 != 0);
+                self.run_for(100);
+            }
+        }
+    }
+
+    #[test]
+    fn tags_events_with_their_source() {
+        let mut h = Harness::new();
+
+        h.detent_cw(1);
+        h.detent_cw(0);
+        h.buttons[1].set(false);
+        h.run_for(100);
+        h.buttons[1].set(true);
+        h.run_for(400);
+
+        assert_eq!(h.events, [
+            InputEvent::new(1, InputAction::Turn(1)),
+            InputEvent::new(0, InputAction::Turn(1)),
+            InputEvent::new(8, InputAction::ShortPress),
+        ]);
+    }
+
+    #[test]
+    fn buttons_keep_their_own_gesture_state() {
+        let mut h = Harness::new();
+
+        // Button 7 is held long while button 8 is clicked twice
+        h.buttons[0].set(false);
+        for _ in 0..2 {
+            h.buttons[1].set(false);
+            h.run_for(60);
+            h.buttons[1].set(true);
+            h.run_for(60);
+        }
+        h.run_for(600);
+        h.buttons[0].set(true);
+        h.run_for(400);
+
+        assert_eq!(h.events, [
+            InputEvent::new(8, InputAction::DoubleClick),
+            InputEvent::new(7, InputAction::LongPress),
+            InputEvent::new(7, InputAction::Repeat),
+        ]);
+    }
+
+    #[test]
+    fn exposes_every_pin_for_interrupts() {
+        let mut h = Harness::new();
+        assert_eq!(h.manager.pins_mut().count(), 2 * 3 + 2);
+    }
+}
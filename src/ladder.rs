@@ -0,0 +1,512 @@
+use embedded_storage::nor_flash::NorFlash;
+use crate::button::{GestureTiming, Gestures};
+use crate::clock::{Duration, Instant};
+use crate::protocol::crc16;
+use crate::screen::{
+    InputEvent, SourceId, WHEEL_MODE, WHEEL_NEXT, WHEEL_PREV, WHEEL_VOLUME_DOWN, WHEEL_VOLUME_UP,
+};
+
+pub const KEYS: usize = 5;
+
+const CALIBRATION_VERSION: u8 = 1;
+pub const CALIBRATION_SIZE: usize = 1 + 2 + 2 + 2 * KEYS + 2;
+// Room for the record rounded up to any flash read or write size
+const STORED_SIZE: usize = 32;
+const UNSET: u16 = 0xFFFF;
+
+// Samples averaged for the idle level before the first key is asked for
+const IDLE_SAMPLES: u32 = 16;
+// A key has to be held for this many samples to be learned
+const MIN_KEY_SAMPLES: u32 = 8;
+// Learning gives up and keeps the old calibration after this long without a key held
+const LEARNING_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum LadderKey {
+    VolumeUp,
+    VolumeDown,
+    Next,
+    Prev,
+    Mode,
+}
+
+impl LadderKey {
+    // Also the order keys are learned in
+    pub const ALL: [LadderKey; KEYS] = [
+        LadderKey::VolumeUp,
+        LadderKey::VolumeDown,
+        LadderKey::Next,
+        LadderKey::Prev,
+        LadderKey::Mode,
+    ];
+
+    pub fn source(&self) -> SourceId {
+        match self {
+            LadderKey::VolumeUp => WHEEL_VOLUME_UP,
+            LadderKey::VolumeDown => WHEEL_VOLUME_DOWN,
+            LadderKey::Next => WHEEL_NEXT,
+            LadderKey::Prev => WHEEL_PREV,
+            LadderKey::Mode => WHEEL_MODE,
+        }
+    }
+
+    fn index(&self) -> usize {
+        *self as usize
+    }
+}
+
+// ADC readings the ladder produces, in raw counts.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct LadderCalibration {
+    // Reading with no key pressed
+    pub idle: u16,
+    // Reading for each key in `LadderKey` order, `None` until learned
+    pub keys: [Option<u16>; KEYS],
+    // Furthest a reading may be from a key's value and still count as that key
+    pub tolerance: u16,
+}
+
+impl Default for LadderCalibration {
+    fn default() -> Self {
+        LadderCalibration {
+            idle: 4095,
+            keys: [None; KEYS],
+            tolerance: 120,
+        }
+    }
+}
+
+impl LadderCalibration {
+    // The key whose value is nearest, if the reading is within tolerance of it and
+    // nearer to it than to the idle level.
+    pub fn classify(&self, reading: u16) -> Option<LadderKey> {
+        let idle_distance = reading.abs_diff(self.idle);
+
+        LadderKey::ALL.iter()
+            .filter_map(|key| self.keys[key.index()].map(|value| (*key, reading.abs_diff(value))))
+            .filter(|(_, distance)| *distance <= self.tolerance && *distance < idle_distance)
+            .min_by_key(|(_, distance)| *distance)
+            .map(|(key, _)| key)
+    }
+
+    pub fn to_bytes(&self) -> [u8; CALIBRATION_SIZE] {
+        let mut out = [0u8; CALIBRATION_SIZE];
+        out[0] = CALIBRATION_VERSION;
+        out[1..3].copy_from_slice(&self.idle.to_be_bytes());
+        out[3..5].copy_from_slice(&self.tolerance.to_be_bytes());
+        for (i, key) in self.keys.iter().enumerate() {
+            out[5 + 2 * i..7 + 2 * i].copy_from_slice(&key.unwrap_or(UNSET).to_be_bytes());
+        }
+
+        let crc = crc16(&out[..CALIBRATION_SIZE - 2]);
+        out[CALIBRATION_SIZE - 2..].copy_from_slice(&crc.to_be_bytes());
+        out
+    }
+
+    // `None` for erased flash, another version or a corrupt record.
+    pub fn from_bytes(bytes: &[u8; CALIBRATION_SIZE]) -> Option<Self> {
+        let crc = u16::from_be_bytes([bytes[CALIBRATION_SIZE - 2], bytes[CALIBRATION_SIZE - 1]]);
+        if bytes[0] != CALIBRATION_VERSION || crc != crc16(&bytes[..CALIBRATION_SIZE - 2]) {
+            return None;
+        }
+
+        let word = |at: usize| u16::from_be_bytes([bytes[at], bytes[at + 1]]);
+        let mut keys = [None; KEYS];
+        for (i, key) in keys.iter_mut().enumerate() {
+            *key = Some(word(5 + 2 * i)).filter(|value| *value != UNSET);
+        }
+
+        Some(LadderCalibration { idle: word(1), tolerance: word(3), keys })
+    }
+
+    // `offset` is the start of a flash sector set aside for the calibration.
+    pub fn load<F: NorFlash>(flash: &mut F, offset: u32) -> Option<Self> {
+        let mut bytes = [0u8; STORED_SIZE];
+        flash.read(offset, &mut bytes[..stored_len(F::READ_SIZE)]).ok()?;
+        Self::from_bytes(bytes[..CALIBRATION_SIZE].try_into().unwrap())
+    }
+
+    // Erases the sector and writes the record at its start. Losing power in between
+    // leaves no calibration, and the defaults are used until the keys are learned again.
+    pub fn save<F: NorFlash>(&self, flash: &mut F, offset: u32) -> Result<(), F::Error> {
+        let mut bytes = [0xFF; STORED_SIZE];
+        bytes[..CALIBRATION_SIZE].copy_from_slice(&self.to_bytes());
+
+        flash.erase(offset, offset + F::ERASE_SIZE as u32)?;
+        flash.write(offset, &bytes[..stored_len(F::WRITE_SIZE)])
+    }
+}
+
+// Bytes to transfer so the record is covered in whole `unit`s
+fn stored_len(unit: usize) -> usize {
+    let len = CALIBRATION_SIZE.next_multiple_of(unit);
+    assert!(len <= STORED_SIZE, "flash unit too large for the calibration buffer");
+    len
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Learning {
+    Idle { sum: u32, count: u32 },
+    Key { step: usize, sum: u32, count: u32 },
+}
+
+// Steering wheel audio controls on a resistor ladder read through the ADC. Each key is
+// its own input source with the usual button gestures.
+pub struct SteeringWheel {
+    calibration: LadderCalibration,
+    timing: GestureTiming,
+    keys: [Gestures; KEYS],
+    learning: Option<Learning>,
+    // Restored if learning is abandoned
+    previous: LadderCalibration,
+    // Start of learning or the last sample with a key held
+    last_held: Option<Instant>,
+    learned: bool,
+}
+
+impl SteeringWheel {
+    pub fn new(calibration: LadderCalibration) -> Self {
+        // No double click window: a wheel key acts as soon as it is let go
+        let timing = GestureTiming {
+            double_click: Duration::ZERO,
+            ..GestureTiming::default()
+        };
+
+        SteeringWheel {
+            calibration,
+            timing,
+            keys: core::array::from_fn(|_| Gestures::new(timing)),
+            learning: None,
+            previous: calibration,
+            last_held: None,
+            learned: false,
+        }
+    }
+
+    pub fn with_timing(mut self, timing: GestureTiming) -> Self {
+        self.timing = timing;
+        self.keys = core::array::from_fn(|_| Gestures::new(timing));
+        self
+    }
+
+    pub fn calibration(&self) -> &LadderCalibration {
+        &self.calibration
+    }
+
+    // Records the idle level, then each key in `LadderKey::ALL` order as it is pressed
+    // and released. Keys produce no events until learning is done. Learning is cancelled
+    // if no key is held for `LEARNING_TIMEOUT`.
+    pub fn start_learning(&mut self) {
+        self.learning = Some(Learning::Idle { sum: 0, count: 0 });
+        self.previous = self.calibration;
+        self.last_held = None;
+    }
+
+    // Stops learning and goes back to the calibration from before it started
+    pub fn cancel_learning(&mut self) {
+        if self.learning.take().is_some() {
+            self.calibration = self.previous;
+        }
+    }
+
+    // The key learning is waiting for, if any
+    pub fn learning(&self) -> Option<LadderKey> {
+        match self.learning? {
+            Learning::Idle { .. } => Some(LadderKey::ALL[0]),
+            Learning::Key { step, .. } => Some(LadderKey::ALL[step]),
+        }
+    }
+
+    pub fn is_learning(&self) -> bool {
+        self.learning.is_some()
+    }
+
+    // Returns the new calibration once, after learning completes, for persisting.
+    pub fn take_learned(&mut self) -> Option<LadderCalibration> {
+        core::mem::take(&mut self.learned).then_some(self.calibration)
+    }
+
+    pub fn update<F>(&mut self, reading: u16, now: Instant, mut emit: F)
+    where
+        F: FnMut(InputEvent)
+    {
+        if self.learning.is_some() {
+            self.learn(reading, now);
+            return;
+        }
+
+        let pressed = self.calibration.classify(reading);
+
+        for (key, gestures) in LadderKey::ALL.iter().zip(self.keys.iter_mut()) {
+            gestures.update(pressed == Some(*key), now, |action| emit(InputEvent::new(key.source(), action)));
+        }
+    }
+
+    fn learn(&mut self, reading: u16, now: Instant) {
+        let away_from_idle = reading.abs_diff(self.calibration.idle) > self.calibration.tolerance;
+
+        let last_held = *self.last_held.get_or_insert(now);
+        if now.duration_since(last_held) >= LEARNING_TIMEOUT {
+            self.cancel_learning();
+            return;
+        }
+        if away_from_idle && matches!(self.learning, Some(Learning::Key { .. })) {
+            self.last_held = Some(now);
+        }
+
+        self.learning = match self.learning {
+            Some(Learning::Idle { sum, count }) => {
+                let (sum, count) = (sum + reading as u32, count + 1);
+                if count < IDLE_SAMPLES {
+                    Some(Learning::Idle { sum, count })
+                } else {
+                    self.calibration.idle = (sum / count) as u16;
+                    Some(Learning::Key { step: 0, sum: 0, count: 0 })
+                }
+            }
+            Some(Learning::Key { step, sum, count }) if away_from_idle => {
+                Some(Learning::Key { step, sum: sum + reading as u32, count: count + 1 })
+            }
+            // Released: keep the key if it was held long enough, otherwise ask again
+            Some(Learning::Key { step, sum, count }) if count >= MIN_KEY_SAMPLES => {
+                self.calibration.keys[step] = Some((sum / count) as u16);
+                if step + 1 < KEYS {
+                    Some(Learning::Key { step: step + 1, sum: 0, count: 0 })
+                } else {
+                    self.learned = true;
+                    None
+                }
+            }
+            Some(Learning::Key { step, .. }) => Some(Learning::Key { step, sum: 0, count: 0 }),
+            None => None,
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec::Vec;
+    use crate::clock::{Clock, MockClock};
+    use crate::mock::MockFlash;
+    use crate::screen::InputAction;
+
+    // Roughly what a five key ladder with a 10k pull-up gives on a 12-bit ADC
+    const LADDER: [u16; KEYS] = [420, 1010, 1650, 2330, 3010];
+
+    fn calibrated() -> LadderCalibration {
+        LadderCalibration {
+            idle: 4000,
+            keys: LADDER.map(Some),
+            tolerance: 150,
+        }
+    }
+
+    // Deterministic noise in -amplitude..=amplitude
+    struct Noise(u32);
+
+    impl Noise {
+        fn next(&mut self, amplitude: u16) -> i32 {
+            self.0 = self.0.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+            ((self.0 >> 16) % (2 * amplitude as u32 + 1)) as i32 - amplitude as i32
+        }
+    }
+
+    struct Harness {
+        wheel: SteeringWheel,
+        clock: MockClock,
+        noise: Noise,
+        events: Vec<InputEvent>,
+    }
+
+    impl Harness {
+        fn new(calibration: LadderCalibration) -> Self {
+            Harness {
+                wheel: SteeringWheel::new(calibration),
+                clock: MockClock::new(),
+                noise: Noise(1),
+                events: Vec::new(),
+            }
+        }
+
+        // Samples `level` every 5 ms with the given noise amplitude
+        fn hold(&mut self, level: u16, ms: u64, amplitude: u16) {
+            for _ in 0..ms / 5 {
+                let reading = (level as i32 + self.noise.next(amplitude)).clamp(0, 4095) as u16;
+                self.wheel.update(reading, self.clock.now(), |e| self.events.push(e));
+                self.clock.advance(Duration::from_millis(5));
+            }
+        }
+    }
+
+    #[test]
+    fn classifies_within_tolerance_only() {
+        let cal = calibrated();
+        assert_eq!(cal.classify(420), Some(LadderKey::VolumeUp));
+        assert_eq!(cal.classify(1100), Some(LadderKey::VolumeDown));
+        assert_eq!(cal.classify(2200), Some(LadderKey::Prev));
+        assert_eq!(cal.classify(3100), Some(LadderKey::Mode));
+
+        // Between two keys, and at rest
+        assert_eq!(cal.classify(1330), None);
+        assert_eq!(cal.classify(4000), None);
+        assert_eq!(cal.classify(3900), None);
+    }
+
+    #[test]
+    fn unlearned_keys_never_match() {
+        let mut cal = calibrated();
+        cal.keys[2] = None;
+        assert_eq!(cal.classify(1650), None);
+        assert_eq!(LadderCalibration::default().classify(420), None);
+    }
+
+    #[test]
+    fn noisy_presses_give_one_event_each() {
+        let mut h = Harness::new(calibrated());
+
+        for level in LADDER {
+            h.hold(4000, 200, 100);
+            h.hold(level, 150, 100);
+        }
+        h.hold(4000, 200, 100);
+
+        let sources: Vec<SourceId> = h.events.iter().map(|e| e.source).collect();
+        assert_eq!(sources, [WHEEL_VOLUME_UP, WHEEL_VOLUME_DOWN, WHEEL_NEXT, WHEEL_PREV, WHEEL_MODE]);
+        assert!(h.events.iter().all(|e| e.action == InputAction::ShortPress));
+    }
+
+    #[test]
+    fn passing_through_other_windows_is_ignored() {
+        let mut h = Harness::new(calibrated());
+
+        // Letting go of Volume Up sweeps the voltage back up past every other key
+        h.hold(4000, 100, 0);
+        h.hold(420, 150, 0);
+        for level in [1010, 1650, 2330, 3010] {
+            h.hold(level, 5, 0);
+        }
+        h.hold(4000, 100, 0);
+
+        assert_eq!(h.events, [InputEvent::new(WHEEL_VOLUME_UP, InputAction::ShortPress)]);
+    }
+
+    #[test]
+    fn holding_a_key_is_a_long_press_then_repeats() {
+        let mut h = Harness::new(calibrated());
+        h.hold(4000, 100, 50);
+        h.hold(1650, 1000, 50);
+        h.hold(4000, 100, 50);
+
+        assert_eq!(h.events, [
+            InputEvent::new(WHEEL_NEXT, InputAction::LongPress),
+            InputEvent::new(WHEEL_NEXT, InputAction::Repeat),
+            InputEvent::new(WHEEL_NEXT, InputAction::Repeat),
+        ]);
+    }
+
+    #[test]
+    fn learns_idle_and_each_key_in_order() {
+        let mut h = Harness::new(LadderCalibration::default());
+        h.wheel.start_learning();
+        assert_eq!(h.wheel.learning(), Some(LadderKey::VolumeUp));
+
+        h.hold(3980, 200, 30);
+        for level in LADDER {
+            h.hold(level, 300, 30);
+            h.hold(3980, 200, 30);
+        }
+
+        assert!(!h.wheel.is_learning());
+        assert!(h.events.is_empty());
+
+        let learned = h.wheel.take_learned().unwrap();
+        assert!(learned.idle.abs_diff(3980) <= 10);
+        for (learned, actual) in learned.keys.iter().zip(LADDER) {
+            assert!(learned.unwrap().abs_diff(actual) <= 10);
+        }
+        assert_eq!(h.wheel.take_learned(), None);
+
+        // And the keys now work
+        h.hold(2330, 100, 30);
+        h.hold(3980, 100, 30);
+        assert_eq!(h.events, [InputEvent::new(WHEEL_PREV, InputAction::ShortPress)]);
+    }
+
+    #[test]
+    fn a_blip_while_learning_is_not_taken_as_a_key() {
+        let mut h = Harness::new(LadderCalibration::default());
+        h.wheel.start_learning();
+        h.hold(3980, 200, 0);
+
+        h.hold(1200, 10, 0);
+        h.hold(3980, 100, 0);
+        assert_eq!(h.wheel.learning(), Some(LadderKey::VolumeUp));
+
+        h.hold(420, 100, 0);
+        h.hold(3980, 100, 0);
+        assert_eq!(h.wheel.learning(), Some(LadderKey::VolumeDown));
+        assert_eq!(h.wheel.calibration().keys[0], Some(420));
+    }
+
+    #[test]
+    fn learning_times_out_without_a_key_held() {
+        let mut h = Harness::new(calibrated());
+        h.wheel.start_learning();
+        h.hold(4000, 200, 0);
+        h.hold(420, 300, 0);
+
+        // Held keys keep learning going, waiting at rest does not
+        h.hold(4000, 9_000, 0);
+        h.hold(1010, 300, 0);
+        h.hold(4000, 9_500, 0);
+        assert_eq!(h.wheel.learning(), Some(LadderKey::Next));
+
+        h.hold(4000, 600, 0);
+        assert!(!h.wheel.is_learning());
+        assert_eq!(h.wheel.calibration(), &calibrated());
+        assert_eq!(h.wheel.take_learned(), None);
+
+        // Back to normal, with the old calibration
+        h.hold(1650, 100, 0);
+        h.hold(4000, 100, 0);
+        assert_eq!(h.events, [InputEvent::new(WHEEL_NEXT, InputAction::ShortPress)]);
+    }
+
+    #[test]
+    fn cancelling_keeps_the_old_calibration() {
+        let mut h = Harness::new(calibrated());
+        h.wheel.start_learning();
+        h.hold(3500, 200, 0);
+        h.hold(600, 300, 0);
+        h.hold(3500, 200, 0);
+        assert_ne!(h.wheel.calibration(), &calibrated());
+
+        h.wheel.cancel_learning();
+        assert!(!h.wheel.is_learning());
+        assert_eq!(h.wheel.calibration(), &calibrated());
+        assert_eq!(h.wheel.take_learned(), None);
+    }
+
+    #[test]
+    fn calibration_round_trips_through_flash() {
+        let mut flash = MockFlash::new(2);
+        let offset = MockFlash::SECTOR_SIZE as u32;
+        assert_eq!(LadderCalibration::load(&mut flash, offset), None);
+
+        let mut cal = calibrated();
+        cal.keys[4] = None;
+        cal.save(&mut flash, offset).unwrap();
+        assert_eq!(LadderCalibration::load(&mut flash, offset), Some(cal));
+
+        // Saving again erases first, since NOR writes can only clear bits
+        cal.keys[4] = Some(3000);
+        cal.save(&mut flash, offset).unwrap();
+        assert_eq!(LadderCalibration::load(&mut flash, offset), Some(cal));
+        assert_eq!(flash.erases, [0, 2]);
+
+        // A flipped bit is caught by the CRC
+        flash.bytes[offset as usize + 6] ^= 0x01;
+        assert_eq!(LadderCalibration::load(&mut flash, offset), None);
+    }
+}
@@ -0,0 +1,32 @@
+#![cfg_attr(not(test), no_std)]
+
+extern crate alloc;
+
+pub mod antenna;
+pub mod button;
+pub mod clock;
+pub mod display;
+pub mod encoder;
+pub mod framebuffer;
+pub mod home;
+pub mod input;
+pub mod ladder;
+pub mod marquee;
+pub mod menu;
+pub mod power;
+pub mod power_monitor;
+pub mod protocol;
+pub mod queue;
+pub mod screen;
+pub mod settings;
+pub mod settings_store;
+pub mod sh1122;
+pub mod source;
+pub mod state;
+pub mod transport;
+
+#[cfg(test)]
+mod mock;
+
+#[cfg(test)]
+mod snapshot;
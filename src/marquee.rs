@@ -0,0 +1,242 @@
+use alloc::string::String;
+use embedded_graphics::Drawable;
+use embedded_graphics::draw_target::DrawTargetExt;
+use embedded_graphics::geometry::{Point, Size};
+use embedded_graphics::mono_font::{MonoFont, MonoTextStyle};
+use embedded_graphics::pixelcolor::Gray4;
+use embedded_graphics::prelude::DrawTarget;
+use embedded_graphics::primitives::Rectangle;
+use embedded_graphics::text::Text;
+use crate::clock::{Duration, Instant};
+use crate::display;
+
+const DEFAULT_SPEED: u32 = 30;
+const DEFAULT_PAUSE: Duration = Duration::from_millis(1500);
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Phase {
+    PauseStart { since: Instant },
+    Forward { since: Instant },
+    PauseEnd { since: Instant },
+    Back { since: Instant },
+}
+
+// Single line of text in a fixed width box. Text that does not fit scrolls across a pixel
+// at a time, pausing at either end, then back again.
+#[derive(Clone)]
+pub struct Marquee {
+    font: &'static MonoFont<'static>,
+    width: u32,
+    // Pixels per second
+    speed: u32,
+    pause: Duration,
+    text: String,
+    phase: Option<Phase>,
+    offset: u32,
+}
+
+impl Marquee {
+    pub fn new(font: &'static MonoFont<'static>, width: u32) -> Self {
+        Marquee {
+            font,
+            width,
+            speed: DEFAULT_SPEED,
+            pause: DEFAULT_PAUSE,
+            text: String::new(),
+            phase: None,
+            offset: 0,
+        }
+    }
+
+    pub fn with_speed(mut self, pixels_per_second: u32) -> Self {
+        self.speed = pixels_per_second;
+        self
+    }
+
+    pub fn with_pause(mut self, pause: Duration) -> Self {
+        self.pause = pause;
+        self
+    }
+
+    // How far the text has scrolled, in pixels
+    pub fn offset(&self) -> u32 {
+        self.offset
+    }
+
+    pub fn is_scrolling(&self) -> bool {
+        matches!(self.phase, Some(Phase::Forward { .. } | Phase::Back { .. }))
+    }
+
+    // Advances the scroll position. Returns true when the text has moved and needs redrawing;
+    // new text starts over from the beginning.
+    pub fn update(&mut self, text: &str, now: Instant) -> bool {
+        if self.phase.is_none() || self.text != text {
+            self.text = String::from(text);
+            self.phase = Some(Phase::PauseStart { since: now });
+            self.offset = 0;
+            return false;
+        }
+
+        let overflow = self.overflow();
+        if overflow == 0 {
+            return false;
+        }
+
+        let previous = self.offset;
+        let travelled = |since: Instant| {
+            (now.duration_since(since).as_millis() * self.speed as u128 / 1000).min(overflow as u128) as u32
+        };
+
+        self.phase = match self.phase {
+            Some(Phase::PauseStart { since }) if now.duration_since(since) >= self.pause => {
+                Some(Phase::Forward { since: now })
+            }
+            Some(Phase::Forward { since }) => {
+                self.offset = travelled(since);
+                if self.offset == overflow { Some(Phase::PauseEnd { since: now }) } else { self.phase }
+            }
+            Some(Phase::PauseEnd { since }) if now.duration_since(since) >= self.pause => {
+                Some(Phase::Back { since: now })
+            }
+            Some(Phase::Back { since }) => {
+                self.offset = overflow - travelled(since);
+                if self.offset == 0 { Some(Phase::PauseStart { since: now }) } else { self.phase }
+            }
+            phase => phase,
+        };
+
+        self.offset != previous
+    }
+
+    // Draws the text with its baseline starting at `position`, clipped to the box. Text that
+    // `update` has not seen yet is drawn from the start.
+    pub fn draw<D>(&self, target: &mut D, text: &str, position: Point, color: Gray4)
+    where
+        D: DrawTarget<Color = Gray4>
+    {
+        let area = Rectangle::new(Point::new(position.x, 0), Size::new(self.width, display::HEIGHT as u32 + 1));
+        let offset = if text == self.text { self.offset } else { 0 };
+
+        Text::new(
+            text,
+            Point::new(position.x - offset as i32, position.y),
+            MonoTextStyle::new(self.font, color)
+        ).draw(&mut target.clipped(&area)).ok();
+    }
+
+    // Pixels of text beyond the box
+    fn overflow(&self) -> u32 {
+        let advance = self.font.character_size.width + self.font.character_spacing;
+        let text_width = (self.text.chars().count() as u32 * advance).saturating_sub(self.font.character_spacing);
+        text_width.saturating_sub(self.width)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use embedded_graphics::mono_font::ascii::FONT_7X13;
+    use crate::clock::{Clock, MockClock};
+    use crate::framebuffer::FrameBuffer;
+
+    // 20 characters of 7 px in a 100 px box leaves 40 px to scroll
+    const LONG: &str = "Clint Eastwood Remix";
+
+    fn marquee() -> Marquee {
+        Marquee::new(&FONT_7X13, 100)
+            .with_speed(20)
+            .with_pause(Duration::from_millis(1000))
+    }
+
+    // Runs updates every 10 ms, returning how many asked for a redraw
+    fn run(marquee: &mut Marquee, text: &str, clock: &MockClock, ms: u64) -> usize {
+        (0..ms / 10)
+            .filter(|_| {
+                clock.advance(Duration::from_millis(10));
+                marquee.update(text, clock.now())
+            })
+            .count()
+    }
+
+    #[test]
+    fn short_text_never_scrolls() {
+        let clock = MockClock::new();
+        let mut marquee = marquee();
+
+        assert_eq!(run(&mut marquee, "Stylo", &clock, 10_000), 0);
+        assert_eq!(marquee.offset(), 0);
+        assert!(!marquee.is_scrolling());
+    }
+
+    #[test]
+    fn scrolls_a_pixel_at_a_time_and_pauses_at_both_ends() {
+        let clock = MockClock::new();
+        let mut marquee = marquee();
+        marquee.update(LONG, clock.now());
+
+        // Holds still at the start
+        assert_eq!(run(&mut marquee, LONG, &clock, 1000), 0);
+
+        // 40 px at 20 px/s: one redraw per pixel
+        assert_eq!(run(&mut marquee, LONG, &clock, 2000), 40);
+        assert_eq!(marquee.offset(), 40);
+        assert!(!marquee.is_scrolling());
+
+        assert_eq!(run(&mut marquee, LONG, &clock, 990), 0);
+        assert_eq!(run(&mut marquee, LONG, &clock, 2010), 40);
+        assert_eq!(marquee.offset(), 0);
+        assert!(!marquee.is_scrolling());
+
+        // And round again
+        assert_eq!(run(&mut marquee, LONG, &clock, 1500), 10);
+        assert!(marquee.is_scrolling());
+    }
+
+    #[test]
+    fn new_text_starts_from_the_beginning() {
+        let clock = MockClock::new();
+        let mut marquee = marquee();
+        marquee.update(LONG, clock.now());
+        run(&mut marquee, LONG, &clock, 1500);
+        assert!(marquee.offset() > 0);
+
+        assert!(!marquee.update("On Melancholy Hill x", clock.now()));
+        assert_eq!(marquee.offset(), 0);
+        assert_eq!(run(&mut marquee, "On Melancholy Hill x", &clock, 900), 0);
+    }
+
+    #[test]
+    fn draws_inside_its_box_only() {
+        let mut fb = FrameBuffer::new();
+        marquee().draw(&mut fb, "################################", Point::new(20, 20), Gray4::new(15));
+
+        let lit = |xs: core::ops::Range<usize>| xs.flat_map(|x| (0..64).map(move |y| (x, y))).any(|(x, y)| fb.pixel(x, y) != 0);
+        assert!(!lit(0..20));
+        assert!(lit(20..27));
+        assert!(lit(113..120));
+        assert!(!lit(120..256));
+    }
+
+    #[test]
+    fn scrolled_text_moves_left() {
+        let clock = MockClock::new();
+        let mut marquee = marquee();
+        marquee.update(LONG, clock.now());
+        run(&mut marquee, LONG, &clock, 1500);
+
+        let mut scrolled = FrameBuffer::new();
+        marquee.draw(&mut scrolled, LONG, Point::new(0, 20), Gray4::new(15));
+
+        // Text the marquee has not been updated with is drawn unscrolled
+        let mut start = FrameBuffer::new();
+        marquee.draw(&mut start, "Clint Eastwood Remix!", Point::new(0, 20), Gray4::new(15));
+
+        let offset = marquee.offset() as usize;
+        assert_eq!(offset, 10);
+        for y in 0..64 {
+            for x in 0..90 {
+                assert_eq!(scrolled.pixel(x, y), start.pixel(x + offset, y));
+            }
+        }
+    }
+}
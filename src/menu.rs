@@ -0,0 +1,286 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+use embedded_graphics::Drawable;
+use embedded_graphics::geometry::{Point, Size};
+use embedded_graphics::mono_font::ascii::{FONT_6X10, FONT_7X13};
+use embedded_graphics::mono_font::MonoTextStyle;
+use embedded_graphics::pixelcolor::Gray4;
+use embedded_graphics::prelude::{DrawTarget, Primitive};
+use embedded_graphics::primitives::{Line, PrimitiveStyle, Rectangle};
+use embedded_graphics::text::{Alignment, Text};
+use crate::clock::Instant;
+use crate::display;
+use crate::screen::{Bindings, InputEvent, Navigation, PRIMARY_ENCODER, Screen, Trigger};
+use crate::state::State;
+
+const TITLE_HEIGHT: i32 = 12;
+const ROW_HEIGHT: i32 = 13;
+pub const VISIBLE_ROWS: usize = ((display::HEIGHT + 1 - TITLE_HEIGHT) / ROW_HEIGHT) as usize;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum MenuAction {
+    Move,
+    Select,
+    Back,
+}
+
+// A row in a `MenuScreen`. Items without a `select` function are shown but do nothing.
+#[derive(Clone)]
+pub struct MenuItem {
+    label: String,
+    // Current value, shown right aligned
+    value: Option<fn(&State) -> String>,
+    select: Option<fn(&State) -> Navigation>,
+}
+
+impl MenuItem {
+    pub fn new(label: &str) -> Self {
+        MenuItem {
+            label: String::from(label),
+            value: None,
+            select: None,
+        }
+    }
+
+    pub fn with_value(mut self, value: fn(&State) -> String) -> Self {
+        self.value = Some(value);
+        self
+    }
+
+    pub fn on_select(mut self, select: fn(&State) -> Navigation) -> Self {
+        self.select = Some(select);
+        self
+    }
+
+    pub fn label(&self) -> &str {
+        &self.label
+    }
+}
+
+// Scrolling list of items: turning moves the cursor, pressing selects and a long press goes back.
+#[derive(Clone)]
+pub struct MenuScreen {
+    title: String,
+    items: Vec<MenuItem>,
+    cursor: usize,
+    // First item on screen
+    top: usize,
+    changed: bool,
+    bindings: Bindings<MenuAction>,
+}
+
+impl MenuScreen {
+    pub fn new(title: &str) -> Self {
+        MenuScreen {
+            title: String::from(title),
+            items: Vec::new(),
+            cursor: 0,
+            top: 0,
+            changed: false,
+            bindings: Bindings::new()
+                .bind(PRIMARY_ENCODER, Trigger::Turn, MenuAction::Move)
+                .bind(PRIMARY_ENCODER, Trigger::ShortPress, MenuAction::Select)
+                .bind(PRIMARY_ENCODER, Trigger::LongPress, MenuAction::Back),
+        }
+    }
+
+    pub fn with_item(mut self, item: MenuItem) -> Self {
+        self.items.push(item);
+        self
+    }
+
+    pub fn with_bindings(mut self, bindings: Bindings<MenuAction>) -> Self {
+        self.bindings = bindings;
+        self
+    }
+
+    // Starts the cursor on an item other than the first. Call after adding the items.
+    pub fn with_cursor(mut self, index: usize) -> Self {
+        self.move_cursor(index as i32 - self.cursor as i32);
+        self
+    }
+
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    // Moves the cursor, stopping at either end, and scrolls to keep it on screen.
+    fn move_cursor(&mut self, steps: i32) {
+        let last = self.items.len().saturating_sub(1) as i32;
+        let cursor = (self.cursor as i32 + steps).clamp(0, last) as usize;
+        if cursor == self.cursor {
+            return;
+        }
+
+        self.cursor = cursor;
+        if cursor < self.top {
+            self.top = cursor;
+        } else if cursor >= self.top + VISIBLE_ROWS {
+            self.top = cursor + 1 - VISIBLE_ROWS;
+        }
+        self.changed = true;
+    }
+}
+
+impl Screen for MenuScreen {
+    fn draw<D>(&self, state: &State, target: &mut D) where D: DrawTarget<Color = Gray4> {
+        Text::with_alignment(
+            self.title.as_str(),
+            Point::new(0, 9),
+            MonoTextStyle::new(&FONT_6X10, Gray4::new(15)),
+            Alignment::Left
+        ).draw(target).ok();
+
+        Line::new(Point::new(0, TITLE_HEIGHT - 2), Point::new(display::WIDTH, TITLE_HEIGHT - 2))
+            .into_styled(PrimitiveStyle::with_stroke(Gray4::new(6), 1))
+            .draw(target).ok();
+
+        let rows = self.items.iter().enumerate().skip(self.top).take(VISIBLE_ROWS);
+        for (row, (index, item)) in rows.enumerate() {
+            let y = TITLE_HEIGHT + row as i32 * ROW_HEIGHT;
+
+            // The selected row is drawn inverted
+            let color = if index == self.cursor {
+                Rectangle::new(Point::new(0, y), Size::new(display::WIDTH as u32 + 1, ROW_HEIGHT as u32))
+                    .into_styled(PrimitiveStyle::with_fill(Gray4::new(15)))
+                    .draw(target).ok();
+                Gray4::new(0)
+            } else {
+                Gray4::new(15)
+            };
+
+            Text::with_alignment(
+                item.label.as_str(),
+                Point::new(2, y + 10),
+                MonoTextStyle::new(&FONT_7X13, color),
+                Alignment::Left
+            ).draw(target).ok();
+
+            if let Some(value) = item.value {
+                Text::with_alignment(
+                    value(state).as_str(),
+                    Point::new(display::WIDTH - 2, y + 10),
+                    MonoTextStyle::new(&FONT_7X13, color),
+                    Alignment::Right
+                ).draw(target).ok();
+            }
+        }
+    }
+
+    fn update(&mut self, _prev_state: &State, _state: &State, _now: Instant) -> bool {
+        core::mem::take(&mut self.changed)
+    }
+
+    fn handle_event(&mut self, state: &State, input: InputEvent) -> Navigation {
+        match self.bindings.lookup(&input) {
+            Some(MenuAction::Move) => {
+                self.move_cursor(input.action.steps());
+                Navigation::Stay
+            }
+            Some(MenuAction::Select) => match self.items.get(self.cursor).and_then(|item| item.select) {
+                Some(select) => select(state),
+                None => Navigation::Stay,
+            },
+            Some(MenuAction::Back) => Navigation::Pop,
+            None => Navigation::Stay,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::format;
+    use crate::framebuffer::FrameBuffer;
+    use crate::screen::InputAction;
+    use crate::state::{ActiveScreen, PowerSetting};
+
+    fn menu(items: usize) -> MenuScreen {
+        (0..items).fold(MenuScreen::new("Test"), |menu, i| menu.with_item(MenuItem::new(&format!("Item {}", i))))
+    }
+
+    fn turn(steps: i32) -> InputEvent {
+        InputEvent::new(PRIMARY_ENCODER, InputAction::Turn(steps))
+    }
+
+    fn press(action: InputAction) -> InputEvent {
+        InputEvent::new(PRIMARY_ENCODER, action)
+    }
+
+    #[test]
+    fn cursor_stops_at_the_ends_and_scrolls() {
+        let state = State::new();
+        let mut menu = menu(7);
+
+        menu.handle_event(&state, turn(-1));
+        assert_eq!(menu.cursor(), 0);
+        assert!(!menu.update(&state, &state, Instant::default()));
+
+        menu.handle_event(&state, turn(3));
+        assert_eq!((menu.cursor, menu.top), (3, 0));
+        assert!(menu.update(&state, &state, Instant::default()));
+        assert!(!menu.update(&state, &state, Instant::default()));
+
+        menu.handle_event(&state, turn(1));
+        assert_eq!((menu.cursor, menu.top), (4, 1));
+
+        menu.handle_event(&state, turn(20));
+        assert_eq!((menu.cursor, menu.top), (6, 3));
+
+        menu.handle_event(&state, turn(-4));
+        assert_eq!((menu.cursor, menu.top), (2, 2));
+    }
+
+    #[test]
+    fn select_runs_the_item_and_long_press_goes_back() {
+        let state = State::new();
+        let mut menu = MenuScreen::new("Test")
+            .with_item(MenuItem::new("Nothing"))
+            .with_item(MenuItem::new("Off").on_select(|state| {
+                state.set_power_setting(PowerSetting::OFF);
+                Navigation::Stay
+            }))
+            .with_item(MenuItem::new("Deeper").on_select(|_| {
+                Navigation::Push(ActiveScreen::Menu(MenuScreen::new("Deeper")))
+            }));
+
+        assert!(matches!(menu.handle_event(&state, press(InputAction::ShortPress)), Navigation::Stay));
+
+        menu.handle_event(&state, turn(1));
+        menu.handle_event(&state, press(InputAction::ShortPress));
+        assert_eq!(state.power_setting(), PowerSetting::OFF);
+
+        menu.handle_event(&state, turn(1));
+        assert!(matches!(
+            menu.handle_event(&state, press(InputAction::ShortPress)),
+            Navigation::Push(ActiveScreen::Menu(_))
+        ));
+        assert!(matches!(menu.handle_event(&state, press(InputAction::LongPress)), Navigation::Pop));
+    }
+
+    #[test]
+    fn empty_menu_ignores_input() {
+        let state = State::new();
+        let mut menu = MenuScreen::new("Empty");
+
+        menu.handle_event(&state, turn(2));
+        assert_eq!(menu.cursor(), 0);
+        assert!(matches!(menu.handle_event(&state, press(InputAction::ShortPress)), Navigation::Stay));
+    }
+
+    #[test]
+    fn highlights_the_selected_row() {
+        let state = State::new();
+        let mut menu = menu(3);
+        menu.handle_event(&state, turn(1));
+
+        let mut fb = FrameBuffer::new();
+        menu.draw(&state, &mut fb);
+
+        // Right hand end of each row, past any text
+        let row_end = |row: i32| fb.pixel(250, (TITLE_HEIGHT + row * ROW_HEIGHT + 1) as usize);
+        assert_eq!(row_end(0), 0);
+        assert_eq!(row_end(1), 15);
+        assert_eq!(row_end(2), 0);
+    }
+}
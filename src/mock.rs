@@ -0,0 +1,324 @@
+// Test doubles shared by the host unit tests.
+
+use alloc::collections::VecDeque;
+use alloc::rc::Rc;
+use alloc::vec::Vec;
+use core::cell::{Cell, RefCell};
+use core::convert::Infallible;
+use embedded_hal::digital::{ErrorType, InputPin, OutputPin, StatefulOutputPin};
+use embedded_hal::i2c::{self, ErrorKind, Operation};
+use embedded_hal::spi::{self, SpiDevice};
+use embedded_storage::nor_flash::{self, NorFlash, NorFlashErrorKind, ReadNorFlash};
+use crate::transport::{Sh1122Error, Transport};
+
+// Digital pin whose level is shared between clones, so a test can keep one handle
+// while the driver under test owns the other.
+#[derive(Clone)]
+pub struct MockPin(Rc<Cell<bool>>);
+
+impl MockPin {
+    pub fn new(high: bool) -> Self {
+        MockPin(Rc::new(Cell::new(high)))
+    }
+
+    pub fn set(&self, high: bool) {
+        self.0.set(high);
+    }
+
+    pub fn get(&self) -> bool {
+        self.0.get()
+    }
+}
+
+impl ErrorType for MockPin {
+    type Error = Infallible;
+}
+
+impl InputPin for MockPin {
+    fn is_high(&mut self) -> Result<bool, Infallible> {
+        Ok(self.0.get())
+    }
+
+    fn is_low(&mut self) -> Result<bool, Infallible> {
+        Ok(!self.0.get())
+    }
+}
+
+impl OutputPin for MockPin {
+    fn set_low(&mut self) -> Result<(), Infallible> {
+        self.0.set(false);
+        Ok(())
+    }
+
+    fn set_high(&mut self) -> Result<(), Infallible> {
+        self.0.set(true);
+        Ok(())
+    }
+}
+
+impl StatefulOutputPin for MockPin {
+    fn is_set_high(&mut self) -> Result<bool, Infallible> {
+        Ok(self.0.get())
+    }
+
+    fn is_set_low(&mut self) -> Result<bool, Infallible> {
+        Ok(!self.0.get())
+    }
+}
+
+pub struct MockI2cBus {
+    // 16-bit big endian register file, as used by INA2xx style devices
+    pub registers: [u16; 256],
+    pub pointer: u8,
+    // Every write transfer as (address, bytes), in order
+    pub writes: Vec<(u8, Vec<u8>)>,
+    // Errors returned by the next transactions, one per transaction, instead of touching the bus
+    pub faults: VecDeque<ErrorKind>,
+}
+
+impl Default for MockI2cBus {
+    fn default() -> Self {
+        MockI2cBus {
+            registers: [0; 256],
+            pointer: 0,
+            writes: Vec::new(),
+            faults: VecDeque::new(),
+        }
+    }
+}
+
+// I2C bus double shared between clones, so tests can inspect the traffic afterwards.
+#[derive(Clone, Default)]
+pub struct MockI2c(Rc<RefCell<MockI2cBus>>);
+
+impl MockI2c {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn bus(&self) -> core::cell::RefMut<'_, MockI2cBus> {
+        self.0.borrow_mut()
+    }
+}
+
+impl i2c::ErrorType for MockI2c {
+    type Error = ErrorKind;
+}
+
+impl i2c::I2c for MockI2c {
+    fn transaction(&mut self, address: u8, operations: &mut [Operation<'_>]) -> Result<(), ErrorKind> {
+        let mut bus = self.0.borrow_mut();
+
+        if let Some(fault) = bus.faults.pop_front() {
+            return Err(fault);
+        }
+
+        for op in operations {
+            match op {
+                Operation::Write(bytes) => {
+                    bus.writes.push((address, bytes.to_vec()));
+
+                    if let [reg, rest @ ..] = bytes {
+                        bus.pointer = *reg;
+                        if let [hi, lo] = rest {
+                            bus.registers[*reg as usize] = u16::from_be_bytes([*hi, *lo]);
+                        }
+                    }
+                }
+                Operation::Read(buf) => {
+                    let value = bus.registers[bus.pointer as usize].to_be_bytes();
+                    for (i, b) in buf.iter_mut().enumerate() {
+                        *b = value[i % 2];
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+struct MockSpiBus {
+    writes: Vec<(bool, Vec<u8>)>,
+    fault: Option<spi::ErrorKind>,
+}
+
+// SPI device double that records each write along with the level of the D/C pin at the time.
+#[derive(Clone)]
+pub struct MockSpi {
+    bus: Rc<RefCell<MockSpiBus>>,
+    dc: MockPin,
+}
+
+impl MockSpi {
+    pub fn new(dc: MockPin) -> Self {
+        MockSpi {
+            bus: Rc::new(RefCell::new(MockSpiBus::default())),
+            dc,
+        }
+    }
+
+    pub fn dc(&self) -> MockPin {
+        self.dc.clone()
+    }
+
+    pub fn writes(&self) -> Vec<(bool, Vec<u8>)> {
+        self.bus.borrow().writes.clone()
+    }
+
+    pub fn fail_next(&self, kind: spi::ErrorKind) {
+        self.bus.borrow_mut().fault = Some(kind);
+    }
+}
+
+impl spi::ErrorType for MockSpi {
+    type Error = spi::ErrorKind;
+}
+
+impl SpiDevice for MockSpi {
+    fn transaction(&mut self, operations: &mut [spi::Operation<'_, u8>]) -> Result<(), spi::ErrorKind> {
+        let mut bus = self.bus.borrow_mut();
+
+        if let Some(fault) = bus.fault.take() {
+            return Err(fault);
+        }
+
+        for op in operations {
+            if let spi::Operation::Write(bytes) = op {
+                bus.writes.push((self.dc.get(), bytes.to_vec()));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+struct MockDmaBus {
+    writes: Vec<(bool, Vec<u8>)>,
+    busy_polls: u32,
+}
+
+// Transport double for a DMA channel: each `start_data` stays in flight for a set number of polls.
+#[derive(Clone)]
+pub struct MockDmaTransport {
+    bus: Rc<RefCell<MockDmaBus>>,
+    transfer_polls: u32,
+}
+
+impl MockDmaTransport {
+    pub fn new(transfer_polls: u32) -> Self {
+        MockDmaTransport {
+            bus: Rc::new(RefCell::new(MockDmaBus::default())),
+            transfer_polls,
+        }
+    }
+
+    // Commands are recorded with `false`, pixel data with `true`
+    pub fn writes(&self) -> Vec<(bool, Vec<u8>)> {
+        self.bus.borrow().writes.clone()
+    }
+}
+
+impl Transport for MockDmaTransport {
+    fn command(&mut self, cmd: &[u8]) -> Result<(), Sh1122Error> {
+        let mut bus = self.bus.borrow_mut();
+        assert_eq!(bus.busy_polls, 0, "command sent while a transfer is in flight");
+        bus.writes.push((false, cmd.to_vec()));
+        Ok(())
+    }
+
+    fn data(&mut self, data: &[u8]) -> Result<(), Sh1122Error> {
+        let mut bus = self.bus.borrow_mut();
+        assert_eq!(bus.busy_polls, 0, "data sent while a transfer is in flight");
+        bus.writes.push((true, data.to_vec()));
+        Ok(())
+    }
+
+    fn start_data(&mut self, data: &[u8]) -> Result<(), Sh1122Error> {
+        self.data(data)?;
+        self.bus.borrow_mut().busy_polls = self.transfer_polls;
+        Ok(())
+    }
+
+    fn poll(&mut self) -> Result<bool, Sh1122Error> {
+        let mut bus = self.bus.borrow_mut();
+        if bus.busy_polls > 0 {
+            bus.busy_polls -= 1;
+            return Ok(false);
+        }
+        Ok(true)
+    }
+}
+
+// NOR flash with small sectors: erasing sets a sector to 0xFF and writes can only clear bits,
+// like the real chip. Counts erases per sector and can cut a write short to simulate power loss.
+pub struct MockFlash {
+    pub bytes: Vec<u8>,
+    pub erases: Vec<u32>,
+    pub writes: u32,
+    // The next write stores only this many bytes and then fails
+    pub tear_next_write: Option<usize>,
+}
+
+impl MockFlash {
+    pub const SECTOR_SIZE: usize = 256;
+
+    pub fn new(sectors: usize) -> Self {
+        MockFlash {
+            bytes: alloc::vec![0xFF; sectors * Self::SECTOR_SIZE],
+            erases: alloc::vec![0; sectors],
+            writes: 0,
+            tear_next_write: None,
+        }
+    }
+}
+
+impl nor_flash::ErrorType for MockFlash {
+    type Error = NorFlashErrorKind;
+}
+
+impl ReadNorFlash for MockFlash {
+    const READ_SIZE: usize = 1;
+
+    fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), NorFlashErrorKind> {
+        nor_flash::check_read(self, offset, bytes.len())?;
+        bytes.copy_from_slice(&self.bytes[offset as usize..offset as usize + bytes.len()]);
+        Ok(())
+    }
+
+    fn capacity(&self) -> usize {
+        self.bytes.len()
+    }
+}
+
+impl NorFlash for MockFlash {
+    const WRITE_SIZE: usize = 4;
+    const ERASE_SIZE: usize = Self::SECTOR_SIZE;
+
+    fn erase(&mut self, from: u32, to: u32) -> Result<(), NorFlashErrorKind> {
+        nor_flash::check_erase(self, from, to)?;
+        self.bytes[from as usize..to as usize].fill(0xFF);
+        for sector in from as usize / Self::SECTOR_SIZE..to as usize / Self::SECTOR_SIZE {
+            self.erases[sector] += 1;
+        }
+        Ok(())
+    }
+
+    fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), NorFlashErrorKind> {
+        nor_flash::check_write(self, offset, bytes.len())?;
+        self.writes += 1;
+
+        let torn = self.tear_next_write.take();
+        let len = torn.unwrap_or(bytes.len()).min(bytes.len());
+        for (dst, src) in self.bytes[offset as usize..].iter_mut().zip(&bytes[..len]) {
+            *dst &= src;
+        }
+
+        match torn {
+            Some(_) => Err(NorFlashErrorKind::Other),
+            None => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,261 @@
+use embedded_hal::digital::{InputPin, OutputPin};
+use crate::clock::{Duration, Instant};
+use crate::state::{PowerSetting, State};
+
+const DEFAULT_DEBOUNCE: Duration = Duration::from_millis(50);
+const DEFAULT_SHUTDOWN_DELAY: Duration = Duration::from_secs(10);
+
+// Drives the accessory power relay from the ACC (ignition) input and the user's `PowerSetting`.
+//
+// ON and OFF force the relay; AUTO follows the debounced ACC signal and keeps the relay
+// on for `shutdown_delay` after ignition off, so cranking dropouts don't power-cycle the unit.
+pub struct PowerController<I, O>
+where
+    I: InputPin,
+    O: OutputPin
+{
+    acc_pin: I,
+    relay_pin: O,
+    acc_active_low: bool,
+    debounce: Duration,
+    shutdown_delay: Duration,
+    acc_raw: bool,
+    acc_raw_since: Option<Instant>,
+    acc_on: bool,
+    acc_off_since: Option<Instant>,
+    relay_on: Option<bool>,
+}
+
+impl<I, O> PowerController<I, O>
+where
+    I: InputPin,
+    O: OutputPin
+{
+    pub fn new(acc_pin: I, relay_pin: O) -> Self {
+        PowerController {
+            acc_pin,
+            relay_pin,
+            acc_active_low: true,
+            debounce: DEFAULT_DEBOUNCE,
+            shutdown_delay: DEFAULT_SHUTDOWN_DELAY,
+            acc_raw: false,
+            acc_raw_since: None,
+            acc_on: false,
+            acc_off_since: None,
+            relay_on: None,
+        }
+    }
+
+    pub fn with_acc_active_low(mut self, active_low: bool) -> Self {
+        self.acc_active_low = active_low;
+        self
+    }
+
+    pub fn with_debounce(mut self, debounce: Duration) -> Self {
+        self.debounce = debounce;
+        self
+    }
+
+    pub fn with_shutdown_delay(mut self, delay: Duration) -> Self {
+        self.shutdown_delay = delay;
+        self
+    }
+
+    pub fn acc_on(&self) -> bool {
+        self.acc_on
+    }
+
+    pub fn relay_on(&self) -> bool {
+        self.relay_on.unwrap_or(false)
+    }
+
+    pub fn update(&mut self, state: &State, now: Instant) {
+        self.sample_acc(now);
+
+        let relay_on = match state.power_setting() {
+            PowerSetting::ON => true,
+            PowerSetting::OFF => false,
+            PowerSetting::AUTO => match self.acc_off_since {
+                _ if self.acc_on => true,
+                Some(off_since) => now.duration_since(off_since) < self.shutdown_delay,
+                None => false,
+            },
+        };
+
+        if self.relay_on != Some(relay_on) {
+            let result = if relay_on { self.relay_pin.set_high() } else { self.relay_pin.set_low() };
+            if result.is_ok() {
+                self.relay_on = Some(relay_on);
+            }
+        }
+
+        state.set_accessory_power(self.relay_on());
+    }
+
+    fn sample_acc(&mut self, now: Instant) {
+        let Ok(high) = self.acc_pin.is_high() else { return; };
+        let raw = high != self.acc_active_low;
+
+        // Trust the very first sample so the relay doesn't glitch off at boot with ignition on
+        if self.acc_raw_since.is_none() {
+            self.acc_raw = raw;
+            self.acc_raw_since = Some(now);
+            self.acc_on = raw;
+            return;
+        }
+
+        if raw != self.acc_raw {
+            self.acc_raw = raw;
+            self.acc_raw_since = Some(now);
+        }
+
+        let stable = self.acc_raw_since
+            .is_some_and(|since| now.duration_since(since) >= self.debounce);
+
+        if stable && self.acc_raw != self.acc_on {
+            self.acc_on = self.acc_raw;
+            self.acc_off_since = if self.acc_on { None } else { Some(now) };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::{Clock, MockClock};
+    use crate::mock::MockPin;
+
+    struct Harness {
+        controller: PowerController<MockPin, MockPin>,
+        acc: MockPin,
+        relay: MockPin,
+        clock: MockClock,
+        state: State,
+    }
+
+    impl Harness {
+        fn new(setting: PowerSetting) -> Self {
+            // Active low ACC input, ignition initially off
+            let acc = MockPin::new(true);
+            let relay = MockPin::new(false);
+            let controller = PowerController::new(acc.clone(), relay.clone())
+                .with_debounce(Duration::from_millis(50))
+                .with_shutdown_delay(Duration::from_secs(5));
+
+            let state = State::new();
+            state.set_power_setting(setting);
+
+            Harness { controller, acc, relay, clock: MockClock::new(), state }
+        }
+
+        fn ignition(&self, on: bool) {
+            self.acc.set(!on);
+        }
+
+        fn run_for(&mut self, ms: u64) {
+            for _ in 0..ms / 10 {
+                self.controller.update(&self.state, self.clock.now());
+                self.clock.advance(Duration::from_millis(10));
+            }
+            self.controller.update(&self.state, self.clock.now());
+        }
+    }
+
+    #[test]
+    fn forced_settings_ignore_acc() {
+        let mut h = Harness::new(PowerSetting::ON);
+        h.run_for(100);
+        assert!(h.relay.get());
+        assert!(h.state.accessory_power());
+
+        h.state.set_power_setting(PowerSetting::OFF);
+        h.ignition(true);
+        h.run_for(100);
+        assert!(!h.relay.get());
+        assert!(!h.state.accessory_power());
+    }
+
+    #[test]
+    fn auto_follows_debounced_acc() {
+        let mut h = Harness::new(PowerSetting::AUTO);
+        h.run_for(100);
+        assert!(!h.relay.get());
+
+        h.ignition(true);
+        h.run_for(30);
+        assert!(!h.relay.get());
+
+        h.run_for(30);
+        assert!(h.relay.get());
+        assert!(h.controller.acc_on());
+        assert!(h.state.accessory_power());
+    }
+
+    #[test]
+    fn auto_ignores_rapid_toggles() {
+        let mut h = Harness::new(PowerSetting::AUTO);
+        h.run_for(100);
+
+        for _ in 0..20 {
+            h.ignition(true);
+            h.run_for(20);
+            h.ignition(false);
+            h.run_for(20);
+        }
+
+        assert!(!h.controller.acc_on());
+        assert!(!h.relay.get());
+    }
+
+    #[test]
+    fn auto_rides_through_cranking_dropout() {
+        let mut h = Harness::new(PowerSetting::AUTO);
+        h.ignition(true);
+        h.run_for(100);
+        assert!(h.relay.get());
+
+        // Starter motor pulls ACC down for a second
+        h.ignition(false);
+        h.run_for(1000);
+        assert!(h.relay.get());
+
+        h.ignition(true);
+        h.run_for(6000);
+        assert!(h.relay.get());
+    }
+
+    #[test]
+    fn auto_switches_off_after_shutdown_delay() {
+        let mut h = Harness::new(PowerSetting::AUTO);
+        h.ignition(true);
+        h.run_for(100);
+
+        h.ignition(false);
+        h.run_for(4900);
+        assert!(h.relay.get());
+        assert!(h.state.accessory_power());
+
+        h.run_for(200);
+        assert!(!h.relay.get());
+        assert!(!h.state.accessory_power());
+    }
+
+    #[test]
+    fn boots_straight_into_on_when_ignition_is_on() {
+        let mut h = Harness::new(PowerSetting::AUTO);
+        h.ignition(true);
+        h.run_for(0);
+        assert!(h.relay.get());
+    }
+
+    #[test]
+    fn switching_to_auto_with_ignition_off_powers_down_immediately() {
+        let mut h = Harness::new(PowerSetting::ON);
+        h.run_for(100);
+        assert!(h.relay.get());
+
+        h.state.set_power_setting(PowerSetting::AUTO);
+        h.run_for(0);
+        assert!(!h.relay.get());
+    }
+}
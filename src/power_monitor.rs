@@ -0,0 +1,356 @@
+use embedded_hal::i2c::I2c;
+use crate::clock::{Duration, Instant};
+use crate::state::State;
+
+const REG_CONFIG: u8 = 0x00;
+const REG_SHUNT_VOLTAGE: u8 = 0x01;
+const REG_BUS_VOLTAGE: u8 = 0x02;
+const REG_CURRENT: u8 = 0x04;
+const REG_CALIBRATION: u8 = 0x05;
+
+// INA219: 32 V bus range, +-320 mV shunt range, 12-bit, continuous shunt and bus
+const INA219_CONFIG: u16 = 0x399F;
+// INA226: 16 sample averaging, 1.1 ms conversions, continuous shunt and bus
+const INA226_CONFIG: u16 = 0x4527;
+
+const DEFAULT_SAMPLE_INTERVAL: Duration = Duration::from_millis(500);
+
+// Readings go into `State` at the precision they are shown with, so noise in the last
+// bits doesn't count as a change and redraw the screen
+const VOLTAGE_STEPS_PER_VOLT: f32 = 10.0;
+const CURRENT_STEPS_PER_AMP: f32 = 100.0;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum MonitorChip {
+    INA219,
+    INA226,
+}
+
+impl MonitorChip {
+    fn config(&self) -> u16 {
+        match self {
+            MonitorChip::INA219 => INA219_CONFIG,
+            MonitorChip::INA226 => INA226_CONFIG,
+        }
+    }
+
+    // Constant from the datasheet calibration equation: CAL = k / (current_lsb * r_shunt)
+    fn calibration_constant(&self) -> f32 {
+        match self {
+            MonitorChip::INA219 => 0.04096,
+            MonitorChip::INA226 => 0.00512,
+        }
+    }
+
+    fn max_calibration(&self) -> u16 {
+        match self {
+            MonitorChip::INA219 => 0xFFFE,
+            MonitorChip::INA226 => 0x7FFF,
+        }
+    }
+
+    fn bus_voltage(&self, raw: u16) -> f32 {
+        match self {
+            MonitorChip::INA219 => (raw >> 3) as f32 * 0.004,
+            MonitorChip::INA226 => raw as f32 * 0.00125,
+        }
+    }
+
+    fn shunt_voltage(&self, raw: u16) -> f32 {
+        match self {
+            MonitorChip::INA219 => raw as i16 as f32 * 0.000_01,
+            MonitorChip::INA226 => raw as i16 as f32 * 0.000_002_5,
+        }
+    }
+}
+
+// Register value and resulting current resolution for a given shunt.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Calibration {
+    pub register: u16,
+    pub current_lsb: f32,
+}
+
+impl Calibration {
+    pub fn new(chip: MonitorChip, shunt_ohms: f32, max_current: f32) -> Self {
+        let k = chip.calibration_constant();
+        let wanted_lsb = max_current / 32768.0;
+
+        let register = (k / (wanted_lsb * shunt_ohms)) as u32;
+        let register = (register.min(chip.max_calibration() as u32) as u16) & chip.max_calibration();
+
+        // Work back from the truncated register so the reported current stays exact
+        Calibration {
+            register,
+            current_lsb: k / (register as f32 * shunt_ohms),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Measurement {
+    pub voltage: f32,
+    pub current: f32,
+}
+
+// INA219/INA226 bus voltage and current monitor, sampled periodically into `State`.
+pub struct PowerMonitor<I>
+where
+    I: I2c
+{
+    i2c: I,
+    addr: u8,
+    chip: MonitorChip,
+    calibration: Calibration,
+    sample_interval: Duration,
+    last_sample: Option<Instant>,
+    initialised: bool,
+}
+
+impl<I> PowerMonitor<I>
+where
+    I: I2c
+{
+    pub fn new(i2c: I, addr: u8, chip: MonitorChip, shunt_ohms: f32, max_current: f32) -> Self {
+        PowerMonitor {
+            i2c,
+            addr,
+            chip,
+            calibration: Calibration::new(chip, shunt_ohms, max_current),
+            sample_interval: DEFAULT_SAMPLE_INTERVAL,
+            last_sample: None,
+            initialised: false,
+        }
+    }
+
+    pub fn with_sample_interval(mut self, interval: Duration) -> Self {
+        self.sample_interval = interval;
+        self
+    }
+
+    pub fn calibration(&self) -> Calibration {
+        self.calibration
+    }
+
+    pub fn init(&mut self) -> Result<(), I::Error> {
+        self.write_register(REG_CONFIG, self.chip.config())?;
+        self.write_register(REG_CALIBRATION, self.calibration.register)?;
+        self.initialised = true;
+        Ok(())
+    }
+
+    pub fn bus_voltage(&mut self) -> Result<f32, I::Error> {
+        let raw = self.read_register(REG_BUS_VOLTAGE)?;
+        Ok(self.chip.bus_voltage(raw))
+    }
+
+    pub fn shunt_voltage(&mut self) -> Result<f32, I::Error> {
+        let raw = self.read_register(REG_SHUNT_VOLTAGE)?;
+        Ok(self.chip.shunt_voltage(raw))
+    }
+
+    pub fn current(&mut self) -> Result<f32, I::Error> {
+        let raw = self.read_register(REG_CURRENT)?;
+        Ok(raw as i16 as f32 * self.calibration.current_lsb)
+    }
+
+    pub fn measure(&mut self) -> Result<Measurement, I::Error> {
+        Ok(Measurement {
+            voltage: self.bus_voltage()?,
+            current: self.current()?,
+        })
+    }
+
+    // Samples once per interval. A failed transfer leaves the previous values in `State`
+    // and re-sends the configuration next time, in case the chip browned out.
+    pub fn update(&mut self, state: &State, now: Instant) -> Result<(), I::Error> {
+        let due = self.last_sample
+            .is_none_or(|last| now.duration_since(last) >= self.sample_interval);
+        if !due {
+            return Ok(());
+        }
+        self.last_sample = Some(now);
+
+        if !self.initialised {
+            self.init()?;
+        }
+
+        match self.measure() {
+            Ok(m) => {
+                state.set_voltage(quantise(m.voltage, VOLTAGE_STEPS_PER_VOLT));
+                state.set_current(quantise(m.current, CURRENT_STEPS_PER_AMP));
+                Ok(())
+            }
+            Err(e) => {
+                self.initialised = false;
+                Err(e)
+            }
+        }
+    }
+
+    fn write_register(&mut self, reg: u8, value: u16) -> Result<(), I::Error> {
+        let [hi, lo] = value.to_be_bytes();
+        self.i2c.write(self.addr, &[reg, hi, lo])
+    }
+
+    fn read_register(&mut self, reg: u8) -> Result<u16, I::Error> {
+        let mut buf = [0u8; 2];
+        self.i2c.write_read(self.addr, &[reg], &mut buf)?;
+        Ok(u16::from_be_bytes(buf))
+    }
+}
+
+// Rounds to the nearest 1 / `steps_per_unit`.
+fn quantise(value: f32, steps_per_unit: f32) -> f32 {
+    let scaled = value * steps_per_unit;
+    let rounded = if scaled < 0.0 { scaled - 0.5 } else { scaled + 0.5 } as i32;
+    rounded as f32 / steps_per_unit
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::{Clock, MockClock};
+    use crate::mock::MockI2c;
+    use alloc::vec::Vec;
+    use embedded_hal_bus::i2c::RefCellDevice;
+
+    fn approx(a: f32, b: f32, tolerance: f32) -> bool {
+        (a - b).abs() <= tolerance
+    }
+
+    #[test]
+    fn ina219_calibration_matches_datasheet_example() {
+        // 0.1 ohm shunt, 3.2 A full scale
+        let cal = Calibration::new(MonitorChip::INA219, 0.1, 3.2);
+        assert_eq!(cal.register, 4194);
+        assert!(approx(cal.current_lsb, 97.66e-6, 0.05e-6));
+    }
+
+    #[test]
+    fn calibration_is_clamped_for_tiny_shunts() {
+        let cal = Calibration::new(MonitorChip::INA226, 0.0001, 0.1);
+        assert_eq!(cal.register, 0x7FFF);
+
+        let cal = Calibration::new(MonitorChip::INA219, 0.0001, 0.1);
+        assert_eq!(cal.register, 0xFFFE);
+    }
+
+    #[test]
+    fn init_writes_config_and_calibration() {
+        let i2c = MockI2c::new();
+        let mut monitor = PowerMonitor::new(i2c.clone(), 0x40, MonitorChip::INA226, 0.002, 20.0);
+        monitor.init().unwrap();
+
+        let bus = i2c.bus();
+        assert_eq!(bus.registers[REG_CONFIG as usize], INA226_CONFIG);
+        assert_eq!(bus.registers[REG_CALIBRATION as usize], monitor.calibration().register);
+        assert!(bus.writes.iter().all(|(addr, _)| *addr == 0x40));
+    }
+
+    #[test]
+    fn ina219_converts_registers() {
+        let i2c = MockI2c::new();
+        let mut monitor = PowerMonitor::new(i2c.clone(), 0x40, MonitorChip::INA219, 0.1, 3.2);
+
+        {
+            let mut bus = i2c.bus();
+            // 13.2 V in 4 mV steps, shifted past the status bits
+            bus.registers[REG_BUS_VOLTAGE as usize] = 3300 << 3;
+            bus.registers[REG_SHUNT_VOLTAGE as usize] = 26000;
+            bus.registers[REG_CURRENT as usize] = 26624;
+        }
+
+        assert!(approx(monitor.bus_voltage().unwrap(), 13.2, 0.001));
+        assert!(approx(monitor.shunt_voltage().unwrap(), 0.26, 0.0001));
+        assert!(approx(monitor.current().unwrap(), 2.6, 0.001));
+    }
+
+    #[test]
+    fn ina226_converts_negative_current() {
+        let i2c = MockI2c::new();
+        let mut monitor = PowerMonitor::new(i2c.clone(), 0x41, MonitorChip::INA226, 0.002, 20.0);
+        let lsb = monitor.calibration().current_lsb;
+
+        {
+            let mut bus = i2c.bus();
+            bus.registers[REG_BUS_VOLTAGE as usize] = 11_520; // 14.4 V
+            bus.registers[REG_CURRENT as usize] = (-(5.0 / lsb) as i16) as u16;
+        }
+
+        let m = monitor.measure().unwrap();
+        assert!(approx(m.voltage, 14.4, 0.001));
+        assert!(approx(m.current, -5.0, 0.001));
+    }
+
+    #[test]
+    fn monitors_share_one_bus() {
+        let i2c = MockI2c::new();
+        let bus = core::cell::RefCell::new(i2c.clone());
+
+        let mut main_battery = PowerMonitor::new(RefCellDevice::new(&bus), 0x40, MonitorChip::INA219, 0.1, 3.2);
+        let mut amplifier = PowerMonitor::new(RefCellDevice::new(&bus), 0x41, MonitorChip::INA226, 0.002, 20.0);
+
+        main_battery.init().unwrap();
+        amplifier.init().unwrap();
+        main_battery.measure().unwrap();
+
+        let addrs: Vec<u8> = i2c.bus().writes.iter().map(|(addr, _)| *addr).collect();
+        assert_eq!(addrs, [0x40, 0x40, 0x41, 0x41, 0x40, 0x40]);
+    }
+
+    #[test]
+    fn update_samples_into_state_at_interval() {
+        let i2c = MockI2c::new();
+        let clock = MockClock::new();
+        let state = State::new();
+        let mut monitor = PowerMonitor::new(i2c.clone(), 0x40, MonitorChip::INA219, 0.1, 3.2)
+            .with_sample_interval(Duration::from_millis(250));
+
+        i2c.bus().registers[REG_BUS_VOLTAGE as usize] = 3000 << 3;
+        monitor.update(&state, clock.now()).unwrap();
+        assert!(approx(state.voltage(), 12.0, 0.001));
+        assert_eq!(i2c.bus().registers[REG_CONFIG as usize], INA219_CONFIG);
+
+        i2c.bus().registers[REG_BUS_VOLTAGE as usize] = 3100 << 3;
+        clock.advance(Duration::from_millis(100));
+        monitor.update(&state, clock.now()).unwrap();
+        assert!(approx(state.voltage(), 12.0, 0.001));
+
+        clock.advance(Duration::from_millis(150));
+        monitor.update(&state, clock.now()).unwrap();
+        assert!(approx(state.voltage(), 12.4, 0.001));
+    }
+
+    #[test]
+    fn noise_below_displayed_precision_is_not_a_change() {
+        let i2c = MockI2c::new();
+        let clock = MockClock::new();
+        let state = State::new();
+        let mut monitor = PowerMonitor::new(i2c.clone(), 0x40, MonitorChip::INA219, 0.1, 3.2);
+
+        // 13.196 V, stored as 13.2 V
+        i2c.bus().registers[REG_BUS_VOLTAGE as usize] = 3299 << 3;
+        i2c.bus().registers[REG_CURRENT as usize] = 26624;
+        monitor.update(&state, clock.now()).unwrap();
+        assert_eq!(state.voltage(), 13.2);
+        assert_eq!(state.current(), 2.6);
+
+        // One LSB either way reads the same
+        let before = state.clone();
+        for raw in [3300, 3298] {
+            i2c.bus().registers[REG_BUS_VOLTAGE as usize] = raw << 3;
+            clock.advance(Duration::from_millis(500));
+            monitor.update(&state, clock.now()).unwrap();
+            assert!(state == before);
+        }
+    }
+
+    #[test]
+    fn quantise_rounds_to_nearest_step() {
+        assert_eq!(quantise(13.196001, 10.0), 13.2);
+        assert_eq!(quantise(13.249, 10.0), 13.2);
+        assert_eq!(quantise(2.6049, 100.0), 2.6);
+        assert_eq!(quantise(-5.0049, 100.0), -5.0);
+    }
+}
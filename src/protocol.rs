@@ -0,0 +1,663 @@
+// Framed binary protocol spoken with the head-unit host over UART0.
+//
+// Frame layout:
+//
+//   START (0x7E) | LEN | TYPE | PAYLOAD (LEN bytes) | CRC16 (big endian)
+//
+// The CRC is CRC-16/CCITT-FALSE computed over LEN, TYPE and PAYLOAD.
+
+use crate::clock::{Duration, Instant};
+use crate::state::{AudioSource, PowerSetting, State, TrackSkip};
+
+pub const START_BYTE: u8 = 0x7E;
+pub const MAX_PAYLOAD: usize = 128;
+
+const HEADER_LEN: usize = 3;
+const CRC_LEN: usize = 2;
+pub const MAX_FRAME: usize = HEADER_LEN + MAX_PAYLOAD + CRC_LEN;
+
+pub const MSG_SET_TRACK_TITLE: u8 = 0x01;
+pub const MSG_SET_TRACK_ARTIST: u8 = 0x02;
+pub const MSG_SET_VOLUME: u8 = 0x03;
+pub const MSG_SET_POWER_SETTING: u8 = 0x04;
+pub const MSG_SET_PLAYBACK: u8 = 0x05;
+pub const MSG_SET_SOURCE: u8 = 0x06;
+pub const MSG_SET_TUNER: u8 = 0x07;
+pub const MSG_SET_BLUETOOTH_DEVICE: u8 = 0x08;
+// Sent to the host rather than received from it
+pub const MSG_SELECT_SOURCE: u8 = 0x20;
+pub const MSG_NEXT_TRACK: u8 = 0x21;
+pub const MSG_PREVIOUS_TRACK: u8 = 0x22;
+pub const MSG_ACK: u8 = 0x80;
+pub const MSG_NACK: u8 = 0x81;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ProtocolError {
+    Crc,
+    Length,
+    UnknownType(u8),
+    InvalidPayload,
+    BufferTooSmall,
+}
+
+impl ProtocolError {
+    pub fn code(&self) -> u8 {
+        match self {
+            ProtocolError::Crc => 0x01,
+            ProtocolError::Length => 0x02,
+            ProtocolError::UnknownType(_) => 0x03,
+            ProtocolError::InvalidPayload => 0x04,
+            ProtocolError::BufferTooSmall => 0x05,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Message<'a> {
+    SetTrackTitle(&'a str),
+    SetTrackArtist(&'a str),
+    SetVolume(u8),
+    SetPowerSetting(PowerSetting),
+    // Times in milliseconds; a duration of zero means the track has no length
+    SetPlayback { position_ms: u32, duration_ms: u32, playing: bool },
+    // The input the host is playing from
+    SetSource(AudioSource),
+    // Frequency in kHz; the station name may be empty
+    SetTuner { frequency_khz: u32, station: &'a str },
+    // Name of the connected phone, empty once it disconnects
+    SetBluetoothDevice(&'a str),
+    // Asks the host to switch input after the user picked a source
+    SelectSource(AudioSource),
+    NextTrack,
+    PreviousTrack,
+    Ack(u8),
+    Nack(u8, u8),
+}
+
+impl<'a> Message<'a> {
+    pub fn msg_type(&self) -> u8 {
+        match self {
+            Message::SetTrackTitle(_) => MSG_SET_TRACK_TITLE,
+            Message::SetTrackArtist(_) => MSG_SET_TRACK_ARTIST,
+            Message::SetVolume(_) => MSG_SET_VOLUME,
+            Message::SetPowerSetting(_) => MSG_SET_POWER_SETTING,
+            Message::SetPlayback { .. } => MSG_SET_PLAYBACK,
+            Message::SetSource(_) => MSG_SET_SOURCE,
+            Message::SetTuner { .. } => MSG_SET_TUNER,
+            Message::SetBluetoothDevice(_) => MSG_SET_BLUETOOTH_DEVICE,
+            Message::SelectSource(_) => MSG_SELECT_SOURCE,
+            Message::NextTrack => MSG_NEXT_TRACK,
+            Message::PreviousTrack => MSG_PREVIOUS_TRACK,
+            Message::Ack(_) => MSG_ACK,
+            Message::Nack(_, _) => MSG_NACK,
+        }
+    }
+
+    pub fn parse(frame: &'a Frame) -> Result<Self, ProtocolError> {
+        let payload = frame.payload();
+
+        match frame.msg_type() {
+            MSG_SET_TRACK_TITLE => core::str::from_utf8(payload)
+                .map(Message::SetTrackTitle)
+                .map_err(|_| ProtocolError::InvalidPayload),
+            MSG_SET_TRACK_ARTIST => core::str::from_utf8(payload)
+                .map(Message::SetTrackArtist)
+                .map_err(|_| ProtocolError::InvalidPayload),
+            MSG_SET_VOLUME => match payload {
+                [v] if *v <= 100 => Ok(Message::SetVolume(*v)),
+                _ => Err(ProtocolError::InvalidPayload),
+            },
+            MSG_SET_POWER_SETTING => match payload {
+                [0] => Ok(Message::SetPowerSetting(PowerSetting::OFF)),
+                [1] => Ok(Message::SetPowerSetting(PowerSetting::ON)),
+                [2] => Ok(Message::SetPowerSetting(PowerSetting::AUTO)),
+                _ => Err(ProtocolError::InvalidPayload),
+            },
+            MSG_SET_PLAYBACK => match payload {
+                [p0, p1, p2, p3, d0, d1, d2, d3, flags] if *flags <= 1 => Ok(Message::SetPlayback {
+                    position_ms: u32::from_be_bytes([*p0, *p1, *p2, *p3]),
+                    duration_ms: u32::from_be_bytes([*d0, *d1, *d2, *d3]),
+                    playing: *flags == 1,
+                }),
+                _ => Err(ProtocolError::InvalidPayload),
+            },
+            MSG_SET_SOURCE => match payload {
+                [code] => source_from_code(*code).map(Message::SetSource),
+                _ => Err(ProtocolError::InvalidPayload),
+            },
+            MSG_SET_TUNER => match payload {
+                [f0, f1, f2, f3, station @ ..] => core::str::from_utf8(station)
+                    .map(|station| Message::SetTuner {
+                        frequency_khz: u32::from_be_bytes([*f0, *f1, *f2, *f3]),
+                        station,
+                    })
+                    .map_err(|_| ProtocolError::InvalidPayload),
+                _ => Err(ProtocolError::InvalidPayload),
+            },
+            MSG_SET_BLUETOOTH_DEVICE => core::str::from_utf8(payload)
+                .map(Message::SetBluetoothDevice)
+                .map_err(|_| ProtocolError::InvalidPayload),
+            MSG_SELECT_SOURCE => match payload {
+                [code] => source_from_code(*code).map(Message::SelectSource),
+                _ => Err(ProtocolError::InvalidPayload),
+            },
+            MSG_NEXT_TRACK => match payload {
+                [] => Ok(Message::NextTrack),
+                _ => Err(ProtocolError::InvalidPayload),
+            },
+            MSG_PREVIOUS_TRACK => match payload {
+                [] => Ok(Message::PreviousTrack),
+                _ => Err(ProtocolError::InvalidPayload),
+            },
+            MSG_ACK => match payload {
+                [t] => Ok(Message::Ack(*t)),
+                _ => Err(ProtocolError::InvalidPayload),
+            },
+            MSG_NACK => match payload {
+                [t, code] => Ok(Message::Nack(*t, *code)),
+                _ => Err(ProtocolError::InvalidPayload),
+            },
+            other => Err(ProtocolError::UnknownType(other)),
+        }
+    }
+
+    pub fn encode(&self, out: &mut [u8]) -> Result<usize, ProtocolError> {
+        let mut scratch = [0u8; MAX_PAYLOAD];
+        let payload: &[u8] = match self {
+            Message::SetTrackTitle(s) | Message::SetTrackArtist(s) | Message::SetBluetoothDevice(s) => s.as_bytes(),
+            Message::SetVolume(v) => {
+                scratch[0] = *v;
+                &scratch[..1]
+            }
+            Message::SetPowerSetting(p) => {
+                scratch[0] = match p {
+                    PowerSetting::OFF => 0,
+                    PowerSetting::ON => 1,
+                    PowerSetting::AUTO => 2,
+                };
+                &scratch[..1]
+            }
+            Message::SetPlayback { position_ms, duration_ms, playing } => {
+                scratch[..4].copy_from_slice(&position_ms.to_be_bytes());
+                scratch[4..8].copy_from_slice(&duration_ms.to_be_bytes());
+                scratch[8] = *playing as u8;
+                &scratch[..9]
+            }
+            Message::SetSource(source) | Message::SelectSource(source) => {
+                scratch[0] = source_code(*source);
+                &scratch[..1]
+            }
+            Message::SetTuner { frequency_khz, station } => {
+                let len = 4 + station.len();
+                if len > MAX_PAYLOAD {
+                    return Err(ProtocolError::Length);
+                }
+                scratch[..4].copy_from_slice(&frequency_khz.to_be_bytes());
+                scratch[4..len].copy_from_slice(station.as_bytes());
+                &scratch[..len]
+            }
+            Message::NextTrack | Message::PreviousTrack => &[],
+            Message::Ack(t) => {
+                scratch[0] = *t;
+                &scratch[..1]
+            }
+            Message::Nack(t, code) => {
+                scratch[..2].copy_from_slice(&[*t, *code]);
+                &scratch[..2]
+            }
+        };
+
+        encode_frame(self.msg_type(), payload, out)
+    }
+}
+
+fn source_code(source: AudioSource) -> u8 {
+    match source {
+        AudioSource::TUNER => 0,
+        AudioSource::BLUETOOTH => 1,
+        AudioSource::AUX => 2,
+        AudioSource::USB => 3,
+    }
+}
+
+fn source_from_code(code: u8) -> Result<AudioSource, ProtocolError> {
+    match code {
+        0 => Ok(AudioSource::TUNER),
+        1 => Ok(AudioSource::BLUETOOTH),
+        2 => Ok(AudioSource::AUX),
+        3 => Ok(AudioSource::USB),
+        _ => Err(ProtocolError::InvalidPayload),
+    }
+}
+
+pub fn encode_frame(msg_type: u8, payload: &[u8], out: &mut [u8]) -> Result<usize, ProtocolError> {
+    if payload.len() > MAX_PAYLOAD {
+        return Err(ProtocolError::Length);
+    }
+
+    let total = HEADER_LEN + payload.len() + CRC_LEN;
+    if out.len() < total {
+        return Err(ProtocolError::BufferTooSmall);
+    }
+
+    out[0] = START_BYTE;
+    out[1] = payload.len() as u8;
+    out[2] = msg_type;
+    out[HEADER_LEN..HEADER_LEN + payload.len()].copy_from_slice(payload);
+
+    let crc = crc16(&out[1..HEADER_LEN + payload.len()]);
+    out[total - 2..total].copy_from_slice(&crc.to_be_bytes());
+
+    Ok(total)
+}
+
+pub fn crc16(data: &[u8]) -> u16 {
+    let mut crc: u16 = 0xFFFF;
+    for &byte in data {
+        crc ^= (byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 { (crc << 1) ^ 0x1021 } else { crc << 1 };
+        }
+    }
+    crc
+}
+
+#[derive(Clone)]
+pub struct Frame {
+    msg_type: u8,
+    len: usize,
+    payload: [u8; MAX_PAYLOAD],
+}
+
+impl Frame {
+    pub fn msg_type(&self) -> u8 {
+        self.msg_type
+    }
+
+    pub fn payload(&self) -> &[u8] {
+        &self.payload[..self.len]
+    }
+}
+
+pub struct Parser {
+    buffer: [u8; MAX_FRAME * 2],
+    len: usize,
+}
+
+impl Default for Parser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Parser {
+    pub fn new() -> Self {
+        Parser {
+            buffer: [0; MAX_FRAME * 2],
+            len: 0,
+        }
+    }
+
+    // Copies as many bytes as fit into the receive buffer and returns how many were taken.
+    // Call `next_frame` until it returns `None` before feeding more data.
+    pub fn feed(&mut self, data: &[u8]) -> usize {
+        let n = data.len().min(self.buffer.len() - self.len);
+        self.buffer[self.len..self.len + n].copy_from_slice(&data[..n]);
+        self.len += n;
+        n
+    }
+
+    pub fn next_frame(&mut self) -> Option<Result<Frame, ProtocolError>> {
+        // Skip any garbage before the next start byte
+        let start = self.buffer[..self.len].iter().position(|&b| b == START_BYTE);
+        match start {
+            Some(i) => self.consume(i),
+            None => {
+                self.len = 0;
+                return None;
+            }
+        }
+
+        if self.len < HEADER_LEN {
+            return None;
+        }
+
+        let payload_len = self.buffer[1] as usize;
+        if payload_len > MAX_PAYLOAD {
+            self.consume(1);
+            return Some(Err(ProtocolError::Length));
+        }
+
+        let total = HEADER_LEN + payload_len + CRC_LEN;
+        if self.len < total {
+            return None;
+        }
+
+        let expected = u16::from_be_bytes([self.buffer[total - 2], self.buffer[total - 1]]);
+        if crc16(&self.buffer[1..total - CRC_LEN]) != expected {
+            // Drop only the start byte so a real frame hidden inside the corrupted one is still found
+            self.consume(1);
+            return Some(Err(ProtocolError::Crc));
+        }
+
+        let mut frame = Frame {
+            msg_type: self.buffer[2],
+            len: payload_len,
+            payload: [0; MAX_PAYLOAD],
+        };
+        frame.payload[..payload_len].copy_from_slice(&self.buffer[HEADER_LEN..HEADER_LEN + payload_len]);
+
+        self.consume(total);
+        Some(Ok(frame))
+    }
+
+    fn consume(&mut self, n: usize) {
+        self.buffer.copy_within(n..self.len, 0);
+        self.len -= n;
+    }
+}
+
+// `now` is when the frame arrived, which playback position reports are counted from.
+pub fn apply(state: &State, frame: &Frame, now: Instant) -> Result<(), ProtocolError> {
+    match Message::parse(frame)? {
+        Message::SetTrackTitle(title) => state.set_track_title(title),
+        Message::SetTrackArtist(artist) => state.set_track_artist(artist),
+        Message::SetVolume(volume) => state.set_volume(volume as u32),
+        Message::SetPowerSetting(setting) => state.set_power_setting(setting),
+        Message::SetPlayback { position_ms, duration_ms, playing } => state.set_playback(
+            Duration::from_millis(position_ms as u64),
+            Duration::from_millis(duration_ms as u64),
+            playing,
+            now,
+        ),
+        Message::SetSource(source) => state.set_audio_source(source),
+        Message::SetTuner { frequency_khz, station } => state.set_tuner(frequency_khz, station),
+        Message::SetBluetoothDevice(name) => state.set_bluetooth_device(name),
+        // Only the host switches inputs and tracks, so there is nothing to do with one sent to us
+        Message::SelectSource(_) | Message::NextTrack | Message::PreviousTrack | Message::Ack(_) | Message::Nack(_, _) => {}
+    }
+
+    Ok(())
+}
+
+// Applies a received frame to the state and returns the acknowledgement to send back, if any.
+pub fn handle(state: &State, result: Result<Frame, ProtocolError>, now: Instant) -> Option<Message<'static>> {
+    match result {
+        // Never acknowledge acknowledgements, or the two ends would echo forever
+        Ok(frame) if matches!(frame.msg_type(), MSG_ACK | MSG_NACK) => None,
+        Ok(frame) => match apply(state, &frame, now) {
+            Ok(()) => Some(Message::Ack(frame.msg_type())),
+            Err(e) => Some(Message::Nack(frame.msg_type(), e.code())),
+        },
+        Err(e) => Some(Message::Nack(0x00, e.code())),
+    }
+}
+
+// Next command for the host caused by something changed on the device, if any.
+pub fn pending_command(state: &State) -> Option<Message<'static>> {
+    let skip = state.take_track_skip().map(|skip| match skip {
+        TrackSkip::Next => Message::NextTrack,
+        TrackSkip::Previous => Message::PreviousTrack,
+    });
+
+    skip.or_else(|| state.take_source_request().map(Message::SelectSource))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encoded(msg: Message) -> ([u8; MAX_FRAME], usize) {
+        let mut out = [0u8; MAX_FRAME];
+        let n = msg.encode(&mut out).unwrap();
+        (out, n)
+    }
+
+    fn drain(parser: &mut Parser) -> ([Option<Result<Frame, ProtocolError>>; 8], usize) {
+        let mut results: [Option<Result<Frame, ProtocolError>>; 8] = Default::default();
+        let mut count = 0;
+        while let Some(result) = parser.next_frame() {
+            results[count] = Some(result);
+            count += 1;
+        }
+        (results, count)
+    }
+
+    #[test]
+    fn crc_matches_reference_value() {
+        assert_eq!(crc16(b"123456789"), 0x29B1);
+    }
+
+    #[test]
+    fn round_trips_every_message() {
+        let messages = [
+            Message::SetTrackTitle("Plastic Beach"),
+            Message::SetTrackArtist("Gorillaz"),
+            Message::SetVolume(42),
+            Message::SetPowerSetting(PowerSetting::AUTO),
+            Message::SetPlayback { position_ms: 83_000, duration_ms: 227_000, playing: true },
+            Message::SetSource(AudioSource::BLUETOOTH),
+            Message::SetTuner { frequency_khz: 92_600, station: "Radio 2" },
+            Message::SetTuner { frequency_khz: 1_008, station: "" },
+            Message::SetBluetoothDevice("Pixel 7"),
+            Message::SelectSource(AudioSource::USB),
+            Message::NextTrack,
+            Message::PreviousTrack,
+            Message::Ack(MSG_SET_VOLUME),
+            Message::Nack(MSG_SET_VOLUME, ProtocolError::InvalidPayload.code()),
+        ];
+
+        for msg in messages {
+            let (bytes, n) = encoded(msg);
+            let mut parser = Parser::new();
+            assert_eq!(parser.feed(&bytes[..n]), n);
+
+            let frame = parser.next_frame().unwrap().unwrap();
+            assert_eq!(Message::parse(&frame).unwrap(), msg);
+            assert!(parser.next_frame().is_none());
+        }
+    }
+
+    #[test]
+    fn waits_for_partial_frames() {
+        let (bytes, n) = encoded(Message::SetTrackTitle("On Melancholy Hill"));
+        let mut parser = Parser::new();
+
+        for (i, byte) in bytes[..n].iter().enumerate() {
+            parser.feed(&[*byte]);
+            let result = parser.next_frame();
+            if i + 1 < n {
+                assert!(result.is_none());
+            } else {
+                let frame = result.unwrap().unwrap();
+                assert_eq!(Message::parse(&frame).unwrap(), Message::SetTrackTitle("On Melancholy Hill"));
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_corrupted_crc() {
+        let (mut bytes, n) = encoded(Message::SetVolume(10));
+        bytes[3] ^= 0xFF;
+
+        let mut parser = Parser::new();
+        parser.feed(&bytes[..n]);
+
+        assert_eq!(parser.next_frame().unwrap().err(), Some(ProtocolError::Crc));
+        assert!(parser.next_frame().is_none());
+    }
+
+    #[test]
+    fn resynchronises_after_garbage_and_bad_frames() {
+        let (mut bad, bad_n) = encoded(Message::SetVolume(10));
+        bad[bad_n - 1] ^= 0x01;
+        let (good, good_n) = encoded(Message::SetVolume(20));
+
+        let mut parser = Parser::new();
+        parser.feed(&[0x00, 0x12, 0x34]);
+        parser.feed(&bad[..bad_n]);
+        parser.feed(&[0xFF]);
+        parser.feed(&good[..good_n]);
+
+        let (results, count) = drain(&mut parser);
+        let frames: usize = results[..count].iter().filter(|r| matches!(r, Some(Ok(_)))).count();
+        assert_eq!(frames, 1);
+
+        let last = results[count - 1].as_ref().unwrap().as_ref().unwrap();
+        assert_eq!(Message::parse(last).unwrap(), Message::SetVolume(20));
+    }
+
+    #[test]
+    fn finds_frame_hidden_inside_corrupted_frame() {
+        let (good, good_n) = encoded(Message::SetVolume(30));
+
+        // A start byte claiming a long payload swallows the real frame that follows it
+        let mut parser = Parser::new();
+        parser.feed(&[START_BYTE, good_n as u8, MSG_SET_TRACK_TITLE]);
+        parser.feed(&good[..good_n]);
+        parser.feed(&[0xAB, 0xCD]);
+
+        assert_eq!(parser.next_frame().unwrap().err(), Some(ProtocolError::Crc));
+        let frame = parser.next_frame().unwrap().unwrap();
+        assert_eq!(Message::parse(&frame).unwrap(), Message::SetVolume(30));
+    }
+
+    #[test]
+    fn rejects_invalid_payloads() {
+        let mut out = [0u8; MAX_FRAME];
+        let n = encode_frame(MSG_SET_VOLUME, &[101], &mut out).unwrap();
+
+        let mut parser = Parser::new();
+        parser.feed(&out[..n]);
+        let frame = parser.next_frame().unwrap().unwrap();
+        assert_eq!(Message::parse(&frame), Err(ProtocolError::InvalidPayload));
+
+        let n = encode_frame(0x55, &[], &mut out).unwrap();
+        parser.feed(&out[..n]);
+        let frame = parser.next_frame().unwrap().unwrap();
+        assert_eq!(Message::parse(&frame), Err(ProtocolError::UnknownType(0x55)));
+    }
+
+    #[test]
+    fn handle_applies_commands_and_acknowledges() {
+        let state = State::new();
+        let mut parser = Parser::new();
+
+        let (bytes, n) = encoded(Message::SetTrackArtist("Blur"));
+        parser.feed(&bytes[..n]);
+        let reply = handle(&state, parser.next_frame().unwrap(), Instant::default());
+        assert_eq!(reply, Some(Message::Ack(MSG_SET_TRACK_ARTIST)));
+        assert_eq!(state.track_artist(), "Blur");
+
+        let (bytes, n) = encoded(Message::SetPowerSetting(PowerSetting::OFF));
+        parser.feed(&bytes[..n]);
+        handle(&state, parser.next_frame().unwrap(), Instant::default());
+        assert_eq!(state.power_setting(), PowerSetting::OFF);
+
+        let (bytes, n) = encoded(Message::Ack(MSG_SET_VOLUME));
+        parser.feed(&bytes[..n]);
+        assert_eq!(handle(&state, parser.next_frame().unwrap(), Instant::default()), None);
+    }
+
+    #[test]
+    fn playback_reports_are_timed_from_arrival() {
+        let state = State::new();
+        let mut parser = Parser::new();
+        let (bytes, n) = encoded(Message::SetPlayback { position_ms: 60_000, duration_ms: 180_000, playing: true });
+        parser.feed(&bytes[..n]);
+
+        let arrived = Instant::from_millis(5_000);
+        assert_eq!(handle(&state, parser.next_frame().unwrap(), arrived), Some(Message::Ack(MSG_SET_PLAYBACK)));
+        assert_eq!(state.track_duration(), Duration::from_secs(180));
+        assert_eq!(state.track_position(arrived + Duration::from_secs(2)), Duration::from_secs(62));
+
+        let mut out = [0u8; MAX_FRAME];
+        let n = encode_frame(MSG_SET_PLAYBACK, &[0, 0, 0, 1, 0, 0, 0, 2, 7], &mut out).unwrap();
+        parser.feed(&out[..n]);
+        let frame = parser.next_frame().unwrap().unwrap();
+        assert_eq!(Message::parse(&frame), Err(ProtocolError::InvalidPayload));
+    }
+
+    #[test]
+    fn handle_applies_source_metadata() {
+        let state = State::new();
+        let mut parser = Parser::new();
+
+        for msg in [
+            Message::SetSource(AudioSource::AUX),
+            Message::SetTuner { frequency_khz: 101_200, station: "3FM" },
+            Message::SetBluetoothDevice("Pixel 7"),
+        ] {
+            let (bytes, n) = encoded(msg);
+            parser.feed(&bytes[..n]);
+            assert_eq!(handle(&state, parser.next_frame().unwrap(), Instant::default()), Some(Message::Ack(msg.msg_type())));
+        }
+
+        assert_eq!(state.audio_source(), AudioSource::AUX);
+        assert_eq!(state.tuner_frequency(), 101_200);
+        assert_eq!(state.tuner_station(), "3FM");
+        assert_eq!(state.bluetooth_device(), "Pixel 7");
+        // Reports from the host are not sent back to it
+        assert_eq!(pending_command(&state), None);
+
+        let mut out = [0u8; MAX_FRAME];
+        let n = encode_frame(MSG_SET_SOURCE, &[4], &mut out).unwrap();
+        parser.feed(&out[..n]);
+        let frame = parser.next_frame().unwrap().unwrap();
+        assert_eq!(Message::parse(&frame), Err(ProtocolError::InvalidPayload));
+    }
+
+    #[test]
+    fn source_picked_on_device_becomes_a_command() {
+        let state = State::new();
+        state.select_audio_source(AudioSource::BLUETOOTH);
+
+        assert_eq!(pending_command(&state), Some(Message::SelectSource(AudioSource::BLUETOOTH)));
+        assert_eq!(pending_command(&state), None);
+    }
+
+    #[test]
+    fn track_skips_become_commands() {
+        let state = State::new();
+        state.skip_track(TrackSkip::Next);
+        state.skip_track(TrackSkip::Next);
+
+        assert_eq!(pending_command(&state), Some(Message::NextTrack));
+        assert_eq!(pending_command(&state), Some(Message::NextTrack));
+        assert_eq!(pending_command(&state), None);
+
+        state.skip_track(TrackSkip::Previous);
+        let (bytes, n) = encoded(pending_command(&state).unwrap());
+        // No payload, just the header and CRC
+        assert_eq!(n, 5);
+        assert_eq!(bytes[..3], [START_BYTE, 0, MSG_PREVIOUS_TRACK]);
+    }
+
+    #[test]
+    fn encode_rejects_oversized_station_name() {
+        let station = core::str::from_utf8(&[b'x'; MAX_PAYLOAD - 3]).unwrap();
+        let mut out = [0u8; MAX_FRAME];
+        assert_eq!(Message::SetTuner { frequency_khz: 92_600, station }.encode(&mut out), Err(ProtocolError::Length));
+    }
+
+    #[test]
+    fn handle_rejects_bad_frames() {
+        let state = State::new();
+        let mut out = [0u8; MAX_FRAME];
+        let n = encode_frame(MSG_SET_VOLUME, &[200], &mut out).unwrap();
+
+        let mut parser = Parser::new();
+        parser.feed(&out[..n]);
+        let reply = handle(&state, parser.next_frame().unwrap(), Instant::default());
+        assert_eq!(reply, Some(Message::Nack(MSG_SET_VOLUME, ProtocolError::InvalidPayload.code())));
+        assert_eq!(state.volume(), 50);
+
+        assert_eq!(handle(&state, Err(ProtocolError::Crc), Instant::default()), Some(Message::Nack(0x00, ProtocolError::Crc.code())));
+    }
+
+    #[test]
+    fn encode_checks_buffer_size() {
+        let mut out = [0u8; 4];
+        assert_eq!(Message::SetVolume(1).encode(&mut out), Err(ProtocolError::BufferTooSmall));
+    }
+}
@@ -0,0 +1,177 @@
+use core::cell::UnsafeCell;
+use core::mem::MaybeUninit;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+// Lock-free single producer, single consumer ring buffer for handing events from an
+// interrupt handler to the main loop. It holds up to `N - 1` items; `split` hands out the
+// only producer and consumer, so each end can live in a different context.
+pub struct EventQueue<T, const N: usize> {
+    slots: [UnsafeCell<MaybeUninit<T>>; N],
+    // Next slot to read, only written by the consumer
+    head: AtomicUsize,
+    // Next slot to write, only written by the producer
+    tail: AtomicUsize,
+    split: AtomicBool,
+}
+
+// The producer and consumer never touch the same slot at the same time: a slot is only read
+// after the producer has published it through `tail`, and only reused after the consumer
+// has released it through `head`.
+unsafe impl<T: Send, const N: usize> Sync for EventQueue<T, N> {}
+
+impl<T, const N: usize> EventQueue<T, N>
+where
+    T: Copy
+{
+    pub const fn new() -> Self {
+        EventQueue {
+            slots: [const { UnsafeCell::new(MaybeUninit::uninit()) }; N],
+            head: AtomicUsize::new(0),
+            tail: AtomicUsize::new(0),
+            split: AtomicBool::new(false),
+        }
+    }
+
+    // Returns `None` if the queue has already been split.
+    pub fn split(&self) -> Option<(Producer<'_, T, N>, Consumer<'_, T, N>)> {
+        if self.split.swap(true, Ordering::AcqRel) {
+            return None;
+        }
+
+        Some((Producer { queue: self }, Consumer { queue: self }))
+    }
+
+    pub fn len(&self) -> usize {
+        let head = self.head.load(Ordering::Acquire);
+        let tail = self.tail.load(Ordering::Acquire);
+        (tail + N - head) % N
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<T, const N: usize> Default for EventQueue<T, N>
+where
+    T: Copy
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub struct Producer<'a, T, const N: usize> {
+    queue: &'a EventQueue<T, N>,
+}
+
+impl<T, const N: usize> Producer<'_, T, N>
+where
+    T: Copy
+{
+    // Hands the item back when the queue is full.
+    pub fn push(&mut self, item: T) -> Result<(), T> {
+        let tail = self.queue.tail.load(Ordering::Relaxed);
+        let next = (tail + 1) % N;
+
+        if next == self.queue.head.load(Ordering::Acquire) {
+            return Err(item);
+        }
+
+        unsafe { (*self.queue.slots[tail].get()).write(item); }
+        self.queue.tail.store(next, Ordering::Release);
+        Ok(())
+    }
+}
+
+pub struct Consumer<'a, T, const N: usize> {
+    queue: &'a EventQueue<T, N>,
+}
+
+impl<T, const N: usize> Consumer<'_, T, N>
+where
+    T: Copy
+{
+    pub fn pop(&mut self) -> Option<T> {
+        let head = self.queue.head.load(Ordering::Relaxed);
+
+        if head == self.queue.tail.load(Ordering::Acquire) {
+            return None;
+        }
+
+        let item = unsafe { (*self.queue.slots[head].get()).assume_init() };
+        self.queue.head.store((head + 1) % N, Ordering::Release);
+        Some(item)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec::Vec;
+
+    #[test]
+    fn splits_only_once() {
+        let queue: EventQueue<u8, 4> = EventQueue::new();
+        assert!(queue.split().is_some());
+        assert!(queue.split().is_none());
+    }
+
+    #[test]
+    fn keeps_order_and_rejects_when_full() {
+        let queue: EventQueue<u8, 4> = EventQueue::new();
+        let (mut tx, mut rx) = queue.split().unwrap();
+
+        assert_eq!(tx.push(1), Ok(()));
+        assert_eq!(tx.push(2), Ok(()));
+        assert_eq!(tx.push(3), Ok(()));
+        assert_eq!(tx.push(4), Err(4));
+        assert_eq!(queue.len(), 3);
+
+        assert_eq!(rx.pop(), Some(1));
+        assert_eq!(tx.push(5), Ok(()));
+
+        let drained: Vec<u8> = core::iter::from_fn(|| rx.pop()).collect();
+        assert_eq!(drained, [2, 3, 5]);
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn wraps_around_many_times() {
+        let queue: EventQueue<u32, 8> = EventQueue::new();
+        let (mut tx, mut rx) = queue.split().unwrap();
+
+        for i in 0..1000 {
+            tx.push(i).unwrap();
+            tx.push(i + 1).unwrap();
+            assert_eq!(rx.pop(), Some(i));
+            assert_eq!(rx.pop(), Some(i + 1));
+        }
+        assert_eq!(rx.pop(), None);
+    }
+
+    #[test]
+    fn producer_and_consumer_on_separate_threads() {
+        use std::thread;
+
+        static QUEUE: EventQueue<u32, 16> = EventQueue::new();
+        let (mut tx, mut rx) = QUEUE.split().unwrap();
+
+        let producer = thread::spawn(move || {
+            for i in 0..10_000 {
+                while tx.push(i).is_err() {
+                    thread::yield_now();
+                }
+            }
+        });
+
+        let mut expected = 0;
+        while expected < 10_000 {
+            if let Some(i) = rx.pop() {
+                assert_eq!(i, expected);
+                expected += 1;
+            }
+        }
+        producer.join().unwrap();
+    }
+}
@@ -0,0 +1,179 @@
+use alloc::vec::Vec;
+use embedded_graphics::draw_target::DrawTarget;
+use embedded_graphics::pixelcolor::Gray4;
+use crate::clock::Instant;
+use crate::state::{ActiveScreen, State};
+
+// Identifies the knob or button an event came from; assigned when it is added to the `InputManager`.
+pub type SourceId = u8;
+
+// The main knob, which every screen can rely on being present
+pub const PRIMARY_ENCODER: SourceId = 0;
+// Tuning/menu knob
+pub const SECONDARY_ENCODER: SourceId = 1;
+
+// Steering wheel audio controls, one source per key on the resistor ladder
+pub const WHEEL_VOLUME_UP: SourceId = 16;
+pub const WHEEL_VOLUME_DOWN: SourceId = 17;
+pub const WHEEL_NEXT: SourceId = 18;
+pub const WHEEL_PREV: SourceId = 19;
+pub const WHEEL_MODE: SourceId = 20;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum InputAction {
+    // Signed step count: positive clockwise, more than one when turned quickly
+    Turn(i32),
+    // Turned while the button is held down
+    PressTurn(i32),
+    ShortPress,
+    LongPress,
+    // Repeats at an interval for as long as a long press is held
+    Repeat,
+    DoubleClick,
+}
+
+// The kind of an `InputAction`, without its step count
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Trigger {
+    Turn,
+    PressTurn,
+    ShortPress,
+    LongPress,
+    Repeat,
+    DoubleClick,
+}
+
+impl InputAction {
+    pub fn trigger(&self) -> Trigger {
+        match self {
+            InputAction::Turn(_) => Trigger::Turn,
+            InputAction::PressTurn(_) => Trigger::PressTurn,
+            InputAction::ShortPress => Trigger::ShortPress,
+            InputAction::LongPress => Trigger::LongPress,
+            InputAction::Repeat => Trigger::Repeat,
+            InputAction::DoubleClick => Trigger::DoubleClick,
+        }
+    }
+
+    // Step count for turns, zero otherwise
+    pub fn steps(&self) -> i32 {
+        match self {
+            InputAction::Turn(steps) | InputAction::PressTurn(steps) => *steps,
+            _ => 0,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct InputEvent {
+    pub source: SourceId,
+    pub action: InputAction,
+}
+
+impl InputEvent {
+    pub fn new(source: SourceId, action: InputAction) -> Self {
+        InputEvent { source, action }
+    }
+}
+
+// Per-source table a screen uses to turn input into its own actions, so which knob or
+// button does what can differ between screens and be rebound without touching them.
+#[derive(Clone, Debug)]
+pub struct Bindings<A>
+where
+    A: Copy
+{
+    entries: Vec<(SourceId, Trigger, A)>,
+}
+
+impl<A> Bindings<A>
+where
+    A: Copy
+{
+    pub fn new() -> Self {
+        Bindings { entries: Vec::new() }
+    }
+
+    // A later binding for the same source and trigger replaces the earlier one.
+    pub fn bind(mut self, source: SourceId, trigger: Trigger, action: A) -> Self {
+        self.entries.retain(|(s, t, _)| (*s, *t) != (source, trigger));
+        self.entries.push((source, trigger, action));
+        self
+    }
+
+    pub fn lookup(&self, event: &InputEvent) -> Option<A> {
+        let trigger = event.action.trigger();
+        self.entries.iter()
+            .find(|(source, t, _)| *source == event.source && *t == trigger)
+            .map(|(_, _, action)| *action)
+    }
+}
+
+impl<A> Default for Bindings<A>
+where
+    A: Copy
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// What a screen asks of the navigation stack after handling input.
+#[derive(Clone)]
+pub enum Navigation {
+    Stay,
+    // Opens a screen on top of this one
+    Push(ActiveScreen),
+    // Goes back to the screen underneath; the bottom screen is never popped
+    Pop,
+    // Swaps this screen for another without growing the stack
+    Replace(ActiveScreen),
+}
+
+pub trait Screen {
+    fn draw<D>(&self, state: &State, target: &mut D) where D: DrawTarget<Color = Gray4>;
+
+    fn update(&mut self, prev_state: &State, state: &State, now: Instant) -> bool;
+
+    fn handle_event(&mut self, state: &State, input: InputEvent) -> Navigation;
+
+    // True while something on screen is moving, so frames should come faster than usual.
+    fn is_animating(&self) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Clone, Copy, Debug, Eq, PartialEq)]
+    enum Action {
+        Volume,
+        Tune,
+        Mute,
+    }
+
+    #[test]
+    fn bindings_match_source_and_trigger() {
+        let bindings = Bindings::new()
+            .bind(0, Trigger::Turn, Action::Volume)
+            .bind(1, Trigger::Turn, Action::Tune)
+            .bind(2, Trigger::ShortPress, Action::Mute);
+
+        assert_eq!(bindings.lookup(&InputEvent::new(0, InputAction::Turn(-3))), Some(Action::Volume));
+        assert_eq!(bindings.lookup(&InputEvent::new(1, InputAction::Turn(1))), Some(Action::Tune));
+        assert_eq!(bindings.lookup(&InputEvent::new(2, InputAction::ShortPress)), Some(Action::Mute));
+        assert_eq!(bindings.lookup(&InputEvent::new(2, InputAction::LongPress)), None);
+        assert_eq!(bindings.lookup(&InputEvent::new(3, InputAction::Turn(1))), None);
+    }
+
+    #[test]
+    fn rebinding_replaces_the_previous_action() {
+        let bindings = Bindings::new()
+            .bind(0, Trigger::Turn, Action::Volume)
+            .bind(0, Trigger::Turn, Action::Tune);
+
+        assert_eq!(bindings.lookup(&InputEvent::new(0, InputAction::Turn(1))), Some(Action::Tune));
+    }
+}
@@ -0,0 +1,76 @@
+use alloc::format;
+use alloc::string::ToString;
+use crate::menu::{MenuItem, MenuScreen};
+use crate::screen::Navigation;
+use crate::source::source_menu;
+use crate::state::{ActiveScreen, PowerSetting};
+
+// Root of the settings menus, opened from the home screen.
+pub fn settings_menu() -> MenuScreen {
+    MenuScreen::new("SETTINGS")
+        .with_item(MenuItem::new("Power")
+            .with_value(|state| power_label(state.power_setting()).to_string())
+            .on_select(|state| {
+                state.set_power_setting(next_power_setting(state.power_setting()));
+                Navigation::Stay
+            }))
+        .with_item(MenuItem::new("Source")
+            .with_value(|state| state.audio_source().label().to_string())
+            .on_select(|state| Navigation::Push(ActiveScreen::Menu(source_menu(state)))))
+        .with_item(MenuItem::new("Power monitor")
+            .on_select(|_| Navigation::Push(ActiveScreen::Menu(power_monitor_menu()))))
+}
+
+pub fn power_monitor_menu() -> MenuScreen {
+    MenuScreen::new("POWER MONITOR")
+        .with_item(MenuItem::new("Voltage").with_value(|state| format!("{:.1} V", state.voltage())))
+        .with_item(MenuItem::new("Current").with_value(|state| format!("{:.2} A", state.current())))
+        .with_item(MenuItem::new("Power").with_value(|state| format!("{} W", (state.voltage() * state.current()) as i32)))
+}
+
+fn power_label(setting: PowerSetting) -> &'static str {
+    match setting {
+        PowerSetting::ON => "ON",
+        PowerSetting::AUTO => "AUTO",
+        PowerSetting::OFF => "OFF",
+    }
+}
+
+fn next_power_setting(setting: PowerSetting) -> PowerSetting {
+    match setting {
+        PowerSetting::ON => PowerSetting::AUTO,
+        PowerSetting::AUTO => PowerSetting::OFF,
+        PowerSetting::OFF => PowerSetting::ON,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::State;
+    use crate::screen::{InputAction, InputEvent, PRIMARY_ENCODER, Screen};
+
+    #[test]
+    fn selecting_cycles_the_power_setting() {
+        let state = State::new();
+        let mut menu = settings_menu();
+        let press = InputEvent::new(PRIMARY_ENCODER, InputAction::ShortPress);
+
+        menu.handle_event(&state, press);
+        assert_eq!(state.power_setting(), PowerSetting::OFF);
+        menu.handle_event(&state, press);
+        menu.handle_event(&state, press);
+        assert_eq!(state.power_setting(), PowerSetting::AUTO);
+    }
+
+    #[test]
+    fn power_monitor_opens_as_a_submenu() {
+        let state = State::new();
+        let mut menu = settings_menu();
+
+        menu.handle_event(&state, InputEvent::new(PRIMARY_ENCODER, InputAction::Turn(2)));
+        let navigation = menu.handle_event(&state, InputEvent::new(PRIMARY_ENCODER, InputAction::ShortPress));
+        assert!(state.navigate(navigation));
+        assert_eq!(state.screen_depth(), 2);
+    }
+}
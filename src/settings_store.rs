@@ -0,0 +1,406 @@
+use embedded_storage::nor_flash::NorFlash;
+use crate::clock::{Duration, Instant};
+use crate::protocol::crc16;
+use crate::state::{PowerSetting, State};
+
+const RECORD_VERSION: u8 = 1;
+pub const RECORD_SIZE: usize = 16;
+
+// Record layout: version, sequence number, then the settings. Bytes up to the CRC are
+// written as zero so a later version can add fields without moving the existing ones.
+const SEQUENCE: usize = 1;
+const VOLUME: usize = 5;
+const POWER_SETTING: usize = 6;
+const CRC: usize = RECORD_SIZE - 2;
+
+const DEFAULT_SAVE_DELAY: Duration = Duration::from_secs(3);
+
+// User preferences that survive a reboot.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Settings {
+    pub volume: u32,
+    pub power_setting: PowerSetting,
+}
+
+impl Settings {
+    pub fn from_state(state: &State) -> Self {
+        Settings {
+            volume: state.volume(),
+            power_setting: state.power_setting(),
+        }
+    }
+
+    pub fn apply(&self, state: &State) {
+        state.set_volume(self.volume);
+        state.set_power_setting(self.power_setting);
+    }
+
+    fn to_record(self, sequence: u32) -> [u8; RECORD_SIZE] {
+        let mut out = [0u8; RECORD_SIZE];
+        out[0] = RECORD_VERSION;
+        out[SEQUENCE..SEQUENCE + 4].copy_from_slice(&sequence.to_be_bytes());
+        out[VOLUME] = self.volume.min(u8::MAX as u32) as u8;
+        out[POWER_SETTING] = match self.power_setting {
+            PowerSetting::ON => 0,
+            PowerSetting::AUTO => 1,
+            PowerSetting::OFF => 2,
+        };
+
+        let crc = crc16(&out[..CRC]);
+        out[CRC..].copy_from_slice(&crc.to_be_bytes());
+        out
+    }
+
+    // Sequence number and settings, or `None` for erased flash, another version or a corrupt record.
+    fn from_record(bytes: &[u8; RECORD_SIZE]) -> Option<(u32, Self)> {
+        let crc = u16::from_be_bytes([bytes[CRC], bytes[CRC + 1]]);
+        if bytes[0] != RECORD_VERSION || crc != crc16(&bytes[..CRC]) {
+            return None;
+        }
+
+        let power_setting = match bytes[POWER_SETTING] {
+            0 => PowerSetting::ON,
+            1 => PowerSetting::AUTO,
+            2 => PowerSetting::OFF,
+            _ => return None,
+        };
+        let sequence = u32::from_be_bytes(bytes[SEQUENCE..SEQUENCE + 4].try_into().unwrap());
+
+        Some((sequence, Settings { volume: bytes[VOLUME] as u32, power_setting }))
+    }
+}
+
+// Keeps `Settings` in a flash partition as a log of records. Each save goes into the next
+// free slot and moves on to the next sector, erasing it, when one fills up. Erases are
+// spread evenly over the partition, and the newest record always survives a failed or
+// interrupted write because it sits in a sector that isn't being erased.
+//
+// Saves wait until the settings have been left alone for `save_delay`, so turning the
+// volume knob costs one write rather than one per step.
+pub struct SettingsStore {
+    offset: u32,
+    size: u32,
+    save_delay: Duration,
+    // Slot the next record goes into, counted from `offset`
+    next_slot: u32,
+    sequence: u32,
+    saved: Option<Settings>,
+    // Settings waiting to be saved and when they last changed
+    pending: Option<(Settings, Instant)>,
+}
+
+impl SettingsStore {
+    // `offset` and `size` must be whole flash sectors, at least two of them.
+    pub fn new(offset: u32, size: u32) -> Self {
+        SettingsStore {
+            offset,
+            size,
+            save_delay: DEFAULT_SAVE_DELAY,
+            next_slot: 0,
+            sequence: 0,
+            saved: None,
+            pending: None,
+        }
+    }
+
+    pub fn with_save_delay(mut self, delay: Duration) -> Self {
+        self.save_delay = delay;
+        self
+    }
+
+    // Finds the newest valid record and carries on writing after it. Call once at boot,
+    // before anything is saved.
+    pub fn load<F: NorFlash>(&mut self, flash: &mut F) -> Option<Settings> {
+        let mut newest: Option<(u32, u32, Settings)> = None;
+
+        for slot in 0..self.slots() {
+            let record = self.read_slot(flash, slot).ok();
+            let Some((sequence, settings)) = record.and_then(|bytes| Settings::from_record(&bytes)) else {
+                continue;
+            };
+
+            if newest.is_none_or(|(newest, _, _)| sequence > newest) {
+                newest = Some((sequence, slot, settings));
+            }
+        }
+
+        let (sequence, slot, settings) = newest?;
+        self.sequence = sequence.wrapping_add(1);
+        self.next_slot = (slot + 1) % self.slots();
+        self.saved = Some(settings);
+        Some(settings)
+    }
+
+    // Saves the settings once they have stopped changing. Returns whether a record was
+    // written; after an error the save is tried again once another delay has passed.
+    pub fn update<F: NorFlash>(&mut self, flash: &mut F, state: &State, now: Instant) -> Result<bool, F::Error> {
+        let settings = Settings::from_state(state);
+        if self.saved == Some(settings) {
+            self.pending = None;
+            return Ok(false);
+        }
+
+        let changed_at = match self.pending {
+            Some((pending, at)) if pending == settings => at,
+            _ => {
+                self.pending = Some((settings, now));
+                now
+            }
+        };
+
+        if now.duration_since(changed_at) < self.save_delay {
+            return Ok(false);
+        }
+
+        self.pending = None;
+        self.save(flash, settings)
+            .inspect_err(|_| self.pending = Some((settings, now)))
+            .map(|()| true)
+    }
+
+    // Saves any unsaved change straight away, for when power is about to go.
+    pub fn flush<F: NorFlash>(&mut self, flash: &mut F, state: &State) -> Result<bool, F::Error> {
+        let settings = Settings::from_state(state);
+        self.pending = None;
+        if self.saved == Some(settings) {
+            return Ok(false);
+        }
+
+        self.save(flash, settings).map(|()| true)
+    }
+
+    fn save<F: NorFlash>(&mut self, flash: &mut F, settings: Settings) -> Result<(), F::Error> {
+        debug_assert!(self.size as usize >= 2 * F::ERASE_SIZE, "settings need at least two sectors");
+        let slots_per_sector = (F::ERASE_SIZE / RECORD_SIZE) as u32;
+
+        // Slots left dirty by an interrupted write are skipped; a sector is erased just
+        // before its first record goes in
+        let mut slot = self.next_slot;
+        loop {
+            if slot.is_multiple_of(slots_per_sector) {
+                let start = self.address(slot);
+                flash.erase(start, start + F::ERASE_SIZE as u32)?;
+                break;
+            }
+
+            if self.read_slot(flash, slot)?.iter().all(|byte| *byte == 0xFF) {
+                break;
+            }
+
+            slot = (slot + 1) % self.slots();
+        }
+
+        // Move on even if the write fails, so the retry doesn't land on a half-written slot
+        self.next_slot = (slot + 1) % self.slots();
+        let record = settings.to_record(self.sequence);
+        self.sequence = self.sequence.wrapping_add(1);
+
+        flash.write(self.address(slot), &record)?;
+        self.saved = Some(settings);
+        Ok(())
+    }
+
+    fn read_slot<F: NorFlash>(&self, flash: &mut F, slot: u32) -> Result<[u8; RECORD_SIZE], F::Error> {
+        let mut bytes = [0u8; RECORD_SIZE];
+        flash.read(self.address(slot), &mut bytes)?;
+        Ok(bytes)
+    }
+
+    fn slots(&self) -> u32 {
+        self.size / RECORD_SIZE as u32
+    }
+
+    fn address(&self, slot: u32) -> u32 {
+        self.offset + slot * RECORD_SIZE as u32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockFlash;
+
+    const SECTORS: usize = 4;
+    const SLOTS_PER_SECTOR: usize = MockFlash::SECTOR_SIZE / RECORD_SIZE;
+
+    fn new_store() -> SettingsStore {
+        SettingsStore::new(0, (SECTORS * MockFlash::SECTOR_SIZE) as u32)
+            .with_save_delay(Duration::from_secs(2))
+    }
+
+    fn state(volume: u32, power_setting: PowerSetting) -> State {
+        let state = State::new();
+        Settings { volume, power_setting }.apply(&state);
+        state
+    }
+
+    fn reload(flash: &mut MockFlash) -> Option<Settings> {
+        new_store().load(flash)
+    }
+
+    #[test]
+    fn record_round_trip() {
+        let settings = Settings { volume: 73, power_setting: PowerSetting::OFF };
+        let record = settings.to_record(0x0102_0304);
+        assert_eq!(Settings::from_record(&record), Some((0x0102_0304, settings)));
+    }
+
+    #[test]
+    fn rejects_erased_corrupt_and_unknown_records() {
+        assert_eq!(Settings::from_record(&[0xFF; RECORD_SIZE]), None);
+
+        let good = Settings { volume: 40, power_setting: PowerSetting::ON }.to_record(7);
+
+        let mut corrupt = good;
+        corrupt[VOLUME] ^= 0x01;
+        assert_eq!(Settings::from_record(&corrupt), None);
+
+        let mut newer = good;
+        newer[0] = RECORD_VERSION + 1;
+        let crc = crc16(&newer[..CRC]);
+        newer[CRC..].copy_from_slice(&crc.to_be_bytes());
+        assert_eq!(Settings::from_record(&newer), None);
+    }
+
+    #[test]
+    fn unformatted_flash_loads_nothing_and_first_save_erases_first_sector() {
+        let mut flash = MockFlash::new(SECTORS);
+        flash.bytes.fill(0x00);
+        let mut store = new_store();
+
+        assert_eq!(store.load(&mut flash), None);
+        assert_eq!(store.flush(&mut flash, &state(30, PowerSetting::ON)), Ok(true));
+        assert_eq!(flash.erases, [1, 0, 0, 0]);
+        assert_eq!(reload(&mut flash), Some(Settings { volume: 30, power_setting: PowerSetting::ON }));
+    }
+
+    #[test]
+    fn load_picks_newest_record() {
+        let mut flash = MockFlash::new(SECTORS);
+        let mut store = new_store();
+        store.load(&mut flash);
+
+        for volume in [10, 20, 30] {
+            store.flush(&mut flash, &state(volume, PowerSetting::AUTO)).unwrap();
+        }
+        assert_eq!(reload(&mut flash).unwrap().volume, 30);
+
+        // Carries on after the newest record rather than overwriting from the start
+        let mut store = new_store();
+        store.load(&mut flash);
+        store.flush(&mut flash, &state(40, PowerSetting::AUTO)).unwrap();
+        assert_eq!(flash.writes, 4);
+        assert_eq!(flash.erases, [1, 0, 0, 0]);
+        assert_eq!(reload(&mut flash).unwrap().volume, 40);
+    }
+
+    #[test]
+    fn saves_once_settings_stop_changing() {
+        let mut flash = MockFlash::new(SECTORS);
+        let mut store = new_store();
+        store.load(&mut flash);
+        let state = State::new();
+
+        // Nothing saved yet, so the defaults count as a change too
+        assert_eq!(store.update(&mut flash, &state, Instant::from_millis(0)), Ok(false));
+
+        // Each volume step restarts the delay
+        for step in 1..=5 {
+            state.set_volume(50 + step);
+            assert_eq!(store.update(&mut flash, &state, Instant::from_millis(step as u64 * 500)), Ok(false));
+        }
+        assert_eq!(store.update(&mut flash, &state, Instant::from_millis(4_400)), Ok(false));
+        assert_eq!(flash.writes, 0);
+
+        assert_eq!(store.update(&mut flash, &state, Instant::from_millis(4_500)), Ok(true));
+        assert_eq!(flash.writes, 1);
+        assert_eq!(reload(&mut flash).unwrap().volume, 55);
+
+        // Unchanged settings are never written again
+        assert_eq!(store.update(&mut flash, &state, Instant::from_millis(60_000)), Ok(false));
+        assert_eq!(flash.writes, 1);
+    }
+
+    #[test]
+    fn change_back_before_delay_skips_write() {
+        let mut flash = MockFlash::new(SECTORS);
+        let mut store = new_store();
+        let state = State::new();
+        store.flush(&mut flash, &state).unwrap();
+
+        state.set_power_setting(PowerSetting::ON);
+        store.update(&mut flash, &state, Instant::from_millis(0)).unwrap();
+        state.set_power_setting(PowerSetting::AUTO);
+        store.update(&mut flash, &state, Instant::from_millis(1_000)).unwrap();
+
+        assert_eq!(store.update(&mut flash, &state, Instant::from_millis(10_000)), Ok(false));
+        assert_eq!(flash.writes, 1);
+    }
+
+    #[test]
+    fn erases_are_spread_over_all_sectors() {
+        let mut flash = MockFlash::new(SECTORS);
+        let mut store = new_store();
+        store.load(&mut flash);
+
+        let saves = 10 * SECTORS * SLOTS_PER_SECTOR + 5;
+        for i in 0..saves {
+            store.flush(&mut flash, &state(i as u32 % 101, PowerSetting::AUTO)).unwrap();
+        }
+
+        assert_eq!(flash.erases, [11, 10, 10, 10]);
+        assert_eq!(reload(&mut flash).unwrap().volume, (saves as u32 - 1) % 101);
+    }
+
+    #[test]
+    fn newest_record_survives_sector_wrap() {
+        let mut flash = MockFlash::new(SECTORS);
+        let mut store = new_store();
+        store.load(&mut flash);
+
+        // Fill every slot, so the next save erases the sector holding the oldest records
+        for i in 0..SECTORS * SLOTS_PER_SECTOR {
+            store.flush(&mut flash, &state(i as u32, PowerSetting::AUTO)).unwrap();
+        }
+        flash.tear_next_write = Some(0);
+        assert!(store.flush(&mut flash, &state(99, PowerSetting::AUTO)).is_err());
+
+        assert_eq!(flash.erases, [2, 1, 1, 1]);
+        assert_eq!(reload(&mut flash).unwrap().volume, (SECTORS * SLOTS_PER_SECTOR - 1) as u32);
+    }
+
+    #[test]
+    fn interrupted_write_keeps_previous_settings() {
+        let mut flash = MockFlash::new(SECTORS);
+        let mut store = new_store();
+        store.load(&mut flash);
+        store.flush(&mut flash, &state(20, PowerSetting::ON)).unwrap();
+
+        flash.tear_next_write = Some(6);
+        assert!(store.flush(&mut flash, &state(80, PowerSetting::OFF)).is_err());
+        assert_eq!(reload(&mut flash), Some(Settings { volume: 20, power_setting: PowerSetting::ON }));
+
+        // After a reboot the half-written slot is skipped rather than written over
+        let mut store = new_store();
+        store.load(&mut flash);
+        store.flush(&mut flash, &state(80, PowerSetting::OFF)).unwrap();
+        assert_eq!(reload(&mut flash), Some(Settings { volume: 80, power_setting: PowerSetting::OFF }));
+        assert_eq!(flash.bytes[2 * RECORD_SIZE], RECORD_VERSION);
+    }
+
+    #[test]
+    fn failed_save_is_retried_after_delay() {
+        let mut flash = MockFlash::new(SECTORS);
+        let mut store = new_store();
+        store.load(&mut flash);
+        let state = state(65, PowerSetting::AUTO);
+
+        store.update(&mut flash, &state, Instant::from_millis(0)).unwrap();
+        flash.tear_next_write = Some(0);
+        assert!(store.update(&mut flash, &state, Instant::from_millis(2_000)).is_err());
+
+        assert_eq!(store.update(&mut flash, &state, Instant::from_millis(3_000)), Ok(false));
+        assert_eq!(store.update(&mut flash, &state, Instant::from_millis(4_000)), Ok(true));
+        assert_eq!(reload(&mut flash).unwrap().volume, 65);
+    }
+}
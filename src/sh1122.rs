@@ -0,0 +1,612 @@
+use embedded_hal::digital::OutputPin;
+use embedded_hal::i2c::I2c;
+use embedded_hal::spi::SpiDevice;
+use embedded_graphics::draw_target::DrawTarget;
+use embedded_graphics::geometry::Size;
+use embedded_graphics::Pixel;
+use embedded_graphics::pixelcolor::Gray4;
+use embedded_graphics::prelude::OriginDimensions;
+use crate::display::Panel;
+use crate::framebuffer::{DirtySpan, FrameBuffer, HEIGHT, ROW_BYTES};
+use crate::transport::{I2cTransport, SpiTransport, Transport};
+
+pub use crate::transport::Sh1122Error;
+
+// How the panel is mounted. NORMAL matches the framebuffer's bottom-up row order with
+// segment remap off and reversed COM scan; ROTATED turns the picture 180 degrees.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Orientation {
+    NORMAL,
+    ROTATED,
+}
+
+const COLUMNS: u8 = ROW_BYTES as u8;
+
+// Rows still to be sent for the frame in flight
+struct Flush {
+    spans: [Option<DirtySpan>; HEIGHT],
+    next: usize,
+}
+
+pub struct Sh1122<T>
+where
+    T: Transport
+{
+    transport: T,
+    orientation: Orientation,
+    column_offset: u8,
+    // Back buffer: drawn into while the front buffer is on its way to the panel
+    buffer: FrameBuffer,
+    // What the panel RAM holds once the flush in flight completes
+    front: FrameBuffer,
+    // Whether `front` is known to match the panel RAM; when not, the next flush sends everything
+    front_valid: bool,
+    flush: Option<Flush>,
+}
+
+impl<I> Sh1122<I2cTransport<I>>
+where
+    I: I2c
+{
+    pub fn new_i2c(i2c: I, addr: u8) -> Self {
+        Sh1122::new(I2cTransport::new(i2c, addr))
+    }
+}
+
+impl<S, DC> Sh1122<SpiTransport<S, DC>>
+where
+    S: SpiDevice,
+    DC: OutputPin
+{
+    pub fn new_spi(spi: S, dc: DC) -> Self {
+        Sh1122::new(SpiTransport::new(spi, dc))
+    }
+}
+
+impl<T> Sh1122<T>
+where
+    T: Transport
+{
+    pub fn new(transport: T) -> Self {
+        Sh1122 {
+            transport,
+            orientation: Orientation::NORMAL,
+            column_offset: 0,
+            buffer: FrameBuffer::new(),
+            front: FrameBuffer::new(),
+            front_valid: false,
+            flush: None,
+        }
+    }
+
+    pub fn with_orientation(mut self, orientation: Orientation) -> Self {
+        self.orientation = orientation;
+        self
+    }
+
+    // Column (pixel pair) where the visible area starts in the controller RAM
+    pub fn with_column_offset(mut self, offset: u8) -> Self {
+        self.column_offset = offset;
+        self
+    }
+
+    pub fn init(&mut self) -> Result<(), Sh1122Error> {
+        self.invalidate();
+
+        let (segment_remap, com_scan) = match self.orientation {
+            Orientation::NORMAL => (0xA0, 0xC8),
+            Orientation::ROTATED => (0xA1, 0xC0),
+        };
+
+        let cmds: [&[u8]; 16] = [
+            &[0xAE],       // display off
+            &[0xD5, 0x50], // display clock divide ratio / oscillator frequency
+            &[0xA8, 0x3F], // multiplex 64
+            &[0xD3, 0x00], // display offset
+            &[0x40],       // start line = 0
+            &[0xAD, 0x81], // DC-DC on
+            &[segment_remap],
+            &[com_scan],
+            &[0x81, 0x80], // contrast
+            &[0xD9, 0x22], // discharge / pre-charge period
+            &[0xDB, 0x35], // VCOM deselect level
+            &[0xDC, 0x35], // VSEGM level
+            &[0x30],       // discharge VSL level
+            &[0xA4],       // display RAM contents
+            &[0xA6],       // normal (not inverted)
+            &[0xAF],       // display on
+        ];
+
+        for cmd in cmds {
+            self.transport.command(cmd)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn clear(&mut self) {
+        self.buffer.clear();
+    }
+
+    // Forget what the panel holds so the next flush sends the whole frame.
+    // Any flush in flight is abandoned.
+    pub fn invalidate(&mut self) {
+        self.flush = None;
+        self.front_valid = false;
+    }
+
+    pub fn flush(&mut self) -> Result<(), Sh1122Error> {
+        self.start_flush()?;
+        while !self.poll_flush()? {}
+        Ok(())
+    }
+
+    pub fn is_flushing(&self) -> bool {
+        self.flush.is_some()
+    }
+
+    // Swaps the drawn frame to the front and queues the rows that differ from the panel.
+    // A flush still in flight is completed first.
+    pub fn start_flush(&mut self) -> Result<(), Sh1122Error> {
+        while !self.poll_flush()? {}
+
+        let mut spans = [None; HEIGHT];
+        if self.front_valid {
+            for span in self.buffer.dirty_spans(&self.front) {
+                spans[span.row] = Some(span);
+            }
+        } else {
+            for (row, span) in spans.iter_mut().enumerate() {
+                *span = Some(DirtySpan { row, start: 0, end: ROW_BYTES });
+            }
+        }
+
+        self.front.clone_from(&self.buffer);
+        self.front_valid = false;
+        self.flush = Some(Flush { spans, next: 0 });
+        Ok(())
+    }
+
+    // Sends rows for as long as the transport keeps up without blocking: a blocking transport
+    // finishes the frame in one call, a DMA transport gets one row started per call.
+    // Returns true once the whole frame is on the panel.
+    pub fn poll_flush(&mut self) -> Result<bool, Sh1122Error> {
+        let result = self.advance_flush();
+        if result.is_err() {
+            // Part of the frame may have reached the panel, so its contents are unknown
+            self.invalidate();
+        }
+        result
+    }
+
+    fn advance_flush(&mut self) -> Result<bool, Sh1122Error> {
+        loop {
+            let Some(flush) = &mut self.flush else { return Ok(true); };
+
+            if !self.transport.poll()? {
+                return Ok(false);
+            }
+
+            match flush.spans[flush.next..].iter().flatten().next().copied() {
+                Some(span) => {
+                    flush.next = span.row + 1;
+                    self.write_span(&span)?;
+                }
+                None => {
+                    self.flush = None;
+                    self.front_valid = true;
+                    return Ok(true);
+                }
+            }
+        }
+    }
+
+    fn write_span(&mut self, span: &DirtySpan) -> Result<(), Sh1122Error> {
+        let len = span.end - span.start;
+        let src = &self.front.row(span.row)[span.start..span.end];
+
+        let mut data = [0u8; ROW_BYTES];
+
+        let (row, column) = match self.orientation {
+            Orientation::NORMAL => {
+                data[..len].copy_from_slice(src);
+                (span.row as u8, span.start as u8)
+            }
+            Orientation::ROTATED => {
+                // Mirror both axes: rows and columns run backwards and the two pixels in each byte swap
+                for (dst, b) in data[..len].iter_mut().zip(src.iter().rev()) {
+                    *dst = b.rotate_left(4);
+                }
+                ((HEIGHT - 1 - span.row) as u8, COLUMNS - span.end as u8)
+            }
+        };
+
+        let column = column.wrapping_add(self.column_offset) & (COLUMNS - 1);
+
+        // Row address is a double byte command; the column address is split in two nibble commands
+        self.transport.command(&[0xB0, row, 0x10 | (column >> 4), column & 0x0F])?;
+        self.transport.start_data(&data[..len])
+    }
+}
+
+impl<T> Panel for Sh1122<T>
+where
+    T: Transport
+{
+    fn init(&mut self) -> Result<(), Sh1122Error> {
+        Sh1122::init(self)
+    }
+
+    fn clear(&mut self) {
+        Sh1122::clear(self)
+    }
+
+    fn flush(&mut self) -> Result<(), Sh1122Error> {
+        Sh1122::flush(self)
+    }
+
+    fn start_flush(&mut self) -> Result<(), Sh1122Error> {
+        Sh1122::start_flush(self)
+    }
+
+    fn poll_flush(&mut self) -> Result<bool, Sh1122Error> {
+        Sh1122::poll_flush(self)
+    }
+}
+
+impl<T> DrawTarget for Sh1122<T>
+where
+    T: Transport
+{
+    type Color = Gray4;
+    type Error = Sh1122Error;
+
+    fn draw_iter<P>(&mut self, pixels: P) -> Result<(), Self::Error>
+    where
+        P: IntoIterator<Item=Pixel<Self::Color>>
+    {
+        self.buffer.draw_iter(pixels).ok();
+        Ok(())
+    }
+}
+
+impl<T> OriginDimensions for Sh1122<T>
+where
+    T: Transport
+{
+    fn size(&self) -> Size {
+        Size::new(256, 64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::framebuffer::BUFFER_SIZE;
+    use crate::mock::{MockDmaTransport, MockI2c, MockPin, MockSpi};
+    use alloc::vec::Vec;
+    use embedded_hal::i2c::{ErrorKind, NoAcknowledgeSource};
+    use embedded_graphics::mono_font::ascii::FONT_7X13_BOLD;
+    use embedded_graphics::mono_font::MonoTextStyle;
+    use embedded_graphics::prelude::*;
+    use embedded_graphics::text::Text;
+
+    // Replays recorded writes into a model of the controller RAM: 64 rows of 128 columns,
+    // the column address incrementing after every data byte
+    fn emulate(writes: &[(u8, Vec<u8>)], ram: &mut [u8; BUFFER_SIZE]) {
+        let (mut row, mut column) = (0usize, 0usize);
+        for (_, bytes) in writes {
+            match bytes.as_slice() {
+                [0x00, 0xB0, r, hi @ 0x10..=0x17, lo @ 0x00..=0x0F] => {
+                    row = *r as usize;
+                    column = ((*hi as usize & 0x07) << 4) | *lo as usize;
+                }
+                [0x40, data @ ..] => {
+                    for b in data {
+                        ram[row * ROW_BYTES + column] = *b;
+                        column = (column + 1) % ROW_BYTES;
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn flush_writes(panel: &mut Sh1122<I2cTransport<MockI2c>>, i2c: &MockI2c) -> Vec<Vec<u8>> {
+        i2c.bus().writes.clear();
+        panel.flush().unwrap();
+        i2c.bus().writes.iter().map(|(_, bytes)| bytes.clone()).collect()
+    }
+
+    fn bytes_sent(i2c: &MockI2c) -> usize {
+        i2c.bus().writes.iter().map(|(_, bytes)| bytes.len()).sum()
+    }
+
+    fn draw_volume<T: Transport>(panel: &mut Sh1122<T>, volume: u32) {
+        panel.clear();
+        Text::new(
+            alloc::format!("{}%", volume).as_str(),
+            Point::new(220, 13),
+            MonoTextStyle::new(&FONT_7X13_BOLD, Gray4::new(15)),
+        ).draw(panel).unwrap();
+    }
+
+    #[test]
+    fn maps_address_nack_separately_from_bus_errors() {
+        let i2c = MockI2c::new();
+        let mut panel = Sh1122::new_i2c(i2c.clone(), 0x3C);
+
+        i2c.bus().faults.push_back(ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address));
+        assert_eq!(panel.init(), Err(Sh1122Error::AddressNack));
+
+        i2c.bus().faults.push_back(ErrorKind::NoAcknowledge(NoAcknowledgeSource::Data));
+        assert_eq!(panel.flush(), Err(Sh1122Error::Bus(ErrorKind::NoAcknowledge(NoAcknowledgeSource::Data))));
+
+        i2c.bus().faults.push_back(ErrorKind::Bus);
+        assert_eq!(panel.flush(), Err(Sh1122Error::Bus(ErrorKind::Bus)));
+
+        assert_eq!(panel.flush(), Ok(()));
+    }
+
+    #[test]
+    fn init_sends_one_command_per_write() {
+        let i2c = MockI2c::new();
+        let mut panel = Sh1122::new_i2c(i2c.clone(), 0x3C);
+        panel.init().unwrap();
+
+        let writes: Vec<Vec<u8>> = i2c.bus().writes.iter().map(|(_, bytes)| bytes.clone()).collect();
+        assert_eq!(writes, [
+            [0x00, 0xAE].to_vec(),
+            [0x00, 0xD5, 0x50].to_vec(),
+            [0x00, 0xA8, 0x3F].to_vec(),
+            [0x00, 0xD3, 0x00].to_vec(),
+            [0x00, 0x40].to_vec(),
+            [0x00, 0xAD, 0x81].to_vec(),
+            [0x00, 0xA0].to_vec(),
+            [0x00, 0xC8].to_vec(),
+            [0x00, 0x81, 0x80].to_vec(),
+            [0x00, 0xD9, 0x22].to_vec(),
+            [0x00, 0xDB, 0x35].to_vec(),
+            [0x00, 0xDC, 0x35].to_vec(),
+            [0x00, 0x30].to_vec(),
+            [0x00, 0xA4].to_vec(),
+            [0x00, 0xA6].to_vec(),
+            [0x00, 0xAF].to_vec(),
+        ]);
+    }
+
+    #[test]
+    fn rotated_init_flips_segment_and_com_direction() {
+        let i2c = MockI2c::new();
+        let mut panel = Sh1122::new_i2c(i2c.clone(), 0x3C).with_orientation(Orientation::ROTATED);
+        panel.init().unwrap();
+
+        let bus = i2c.bus();
+        assert_eq!(bus.writes[6].1, [0x00, 0xA1]);
+        assert_eq!(bus.writes[7].1, [0x00, 0xC0]);
+    }
+
+    #[test]
+    fn full_flush_addresses_every_row() {
+        let i2c = MockI2c::new();
+        let mut panel = Sh1122::new_i2c(i2c.clone(), 0x3C);
+        let writes = flush_writes(&mut panel, &i2c);
+
+        assert_eq!(writes.len(), HEIGHT * 2);
+        for (row, pair) in writes.chunks(2).enumerate() {
+            assert_eq!(pair[0], [0x00, 0xB0, row as u8, 0x10, 0x00]);
+            assert_eq!(pair[1].len(), 1 + ROW_BYTES);
+            assert_eq!(pair[1][0], 0x40);
+        }
+    }
+
+    #[test]
+    fn partial_flush_is_byte_exact() {
+        let i2c = MockI2c::new();
+        let mut panel = Sh1122::new_i2c(i2c.clone(), 0x3C);
+        panel.flush().unwrap();
+
+        // Top-left pixel: last buffer row, high nibble of column 0
+        Pixel(Point::new(0, 0), Gray4::new(15)).draw(&mut panel).unwrap();
+        // Pixels 34 and 37 on line 10: buffer row 53, columns 17 and 18
+        Pixel(Point::new(34, 10), Gray4::new(7)).draw(&mut panel).unwrap();
+        Pixel(Point::new(37, 10), Gray4::new(2)).draw(&mut panel).unwrap();
+
+        assert_eq!(flush_writes(&mut panel, &i2c), [
+            [0x00, 0xB0, 53, 0x11, 0x01].to_vec(),
+            [0x40, 0x70, 0x02].to_vec(),
+            [0x00, 0xB0, 63, 0x10, 0x00].to_vec(),
+            [0x40, 0xF0].to_vec(),
+        ]);
+    }
+
+    #[test]
+    fn column_offset_shifts_column_address() {
+        let i2c = MockI2c::new();
+        let mut panel = Sh1122::new_i2c(i2c.clone(), 0x3C).with_column_offset(8);
+        panel.flush().unwrap();
+
+        Pixel(Point::new(255, 63), Gray4::new(9)).draw(&mut panel).unwrap();
+        Pixel(Point::new(20, 63), Gray4::new(9)).draw(&mut panel).unwrap();
+
+        // Column 10 + 8 = 18, and the span end wraps around the 128 column RAM
+        assert_eq!(flush_writes(&mut panel, &i2c)[0], [0x00, 0xB0, 0, 0x11, 0x02]);
+    }
+
+    #[test]
+    fn rotated_flush_mirrors_rows_columns_and_nibbles() {
+        let i2c = MockI2c::new();
+        let mut panel = Sh1122::new_i2c(i2c.clone(), 0x3C).with_orientation(Orientation::ROTATED);
+        panel.flush().unwrap();
+
+        Pixel(Point::new(0, 0), Gray4::new(15)).draw(&mut panel).unwrap();
+        Pixel(Point::new(3, 0), Gray4::new(5)).draw(&mut panel).unwrap();
+
+        // Buffer row 63 columns 0..2 become panel row 0, columns 126..128, reversed with nibbles swapped
+        assert_eq!(flush_writes(&mut panel, &i2c), [
+            [0x00, 0xB0, 0, 0x17, 0x0E].to_vec(),
+            [0x40, 0x50, 0x0F].to_vec(),
+        ]);
+    }
+
+    #[test]
+    fn unchanged_frame_sends_nothing() {
+        let i2c = MockI2c::new();
+        let mut panel = Sh1122::new_i2c(i2c.clone(), 0x3C);
+        draw_volume(&mut panel, 50);
+        panel.flush().unwrap();
+
+        i2c.bus().writes.clear();
+        draw_volume(&mut panel, 50);
+        panel.flush().unwrap();
+        assert_eq!(bytes_sent(&i2c), 0);
+    }
+
+    #[test]
+    fn partial_flush_matches_full_flush() {
+        let partial_i2c = MockI2c::new();
+        let mut partial = Sh1122::new_i2c(partial_i2c.clone(), 0x3C);
+
+        draw_volume(&mut partial, 50);
+        partial.flush().unwrap();
+        let full_frame_bytes = bytes_sent(&partial_i2c);
+
+        for volume in [52, 54, 100, 0] {
+            let before = bytes_sent(&partial_i2c);
+            draw_volume(&mut partial, volume);
+            partial.flush().unwrap();
+
+            // A changed number only touches a narrow strip of a few rows
+            assert!((bytes_sent(&partial_i2c) - before) * 20 < full_frame_bytes);
+        }
+
+        let full_i2c = MockI2c::new();
+        let mut full = Sh1122::new_i2c(full_i2c.clone(), 0x3C);
+        draw_volume(&mut full, 0);
+        full.flush().unwrap();
+
+        let mut partial_ram = [0u8; BUFFER_SIZE];
+        let mut full_ram = [0u8; BUFFER_SIZE];
+        emulate(&partial_i2c.bus().writes, &mut partial_ram);
+        emulate(&full_i2c.bus().writes, &mut full_ram);
+
+        assert_eq!(partial_ram, full_ram);
+        assert_eq!(&partial_ram, full.buffer.as_bytes());
+    }
+
+    #[test]
+    fn init_and_errors_force_a_full_flush() {
+        let i2c = MockI2c::new();
+        let mut panel = Sh1122::new_i2c(i2c.clone(), 0x3C);
+        panel.flush().unwrap();
+        let full_frame_bytes = bytes_sent(&i2c);
+
+        i2c.bus().writes.clear();
+        panel.init().unwrap();
+        i2c.bus().writes.clear();
+        panel.flush().unwrap();
+        assert_eq!(bytes_sent(&i2c), full_frame_bytes);
+
+        i2c.bus().faults.push_back(ErrorKind::Bus);
+        Pixel(Point::new(0, 0), Gray4::new(3)).draw(&mut panel).unwrap();
+        assert!(panel.flush().is_err());
+        i2c.bus().writes.clear();
+        panel.flush().unwrap();
+        assert_eq!(bytes_sent(&i2c), full_frame_bytes);
+    }
+
+    #[test]
+    fn spi_sends_the_same_stream_with_dc_instead_of_control_bytes() {
+        let i2c = MockI2c::new();
+        let mut over_i2c = Sh1122::new_i2c(i2c.clone(), 0x3C);
+        let spi = MockSpi::new(MockPin::new(true));
+        let mut over_spi = Sh1122::new_spi(spi.clone(), spi.dc());
+
+        over_i2c.init().unwrap();
+        draw_volume(&mut over_i2c, 42);
+        over_i2c.flush().unwrap();
+
+        over_spi.init().unwrap();
+        draw_volume(&mut over_spi, 42);
+        over_spi.flush().unwrap();
+
+        let expected: Vec<(bool, Vec<u8>)> = i2c.bus().writes.iter()
+            .map(|(_, bytes)| (bytes[0] == 0x40, bytes[1..].to_vec()))
+            .collect();
+        assert_eq!(spi.writes(), expected);
+    }
+
+    #[test]
+    fn spi_errors_force_a_full_flush() {
+        let spi = MockSpi::new(MockPin::new(true));
+        let mut panel = Sh1122::new_spi(spi.clone(), spi.dc());
+        panel.flush().unwrap();
+
+        spi.fail_next(embedded_hal::spi::ErrorKind::Overrun);
+        Pixel(Point::new(0, 0), Gray4::new(3)).draw(&mut panel).unwrap();
+        assert_eq!(panel.flush(), Err(Sh1122Error::Spi(embedded_hal::spi::ErrorKind::Overrun)));
+
+        let before = spi.writes().len();
+        panel.flush().unwrap();
+        assert_eq!(spi.writes().len() - before, 2 * HEIGHT);
+    }
+
+    #[test]
+    fn dma_flush_starts_one_row_per_poll() {
+        let dma = MockDmaTransport::new(3);
+        let mut panel = Sh1122::new(dma.clone());
+
+        panel.start_flush().unwrap();
+        assert!(panel.is_flushing());
+        assert!(!panel.poll_flush().unwrap());
+        assert_eq!(dma.writes().len(), 2);
+
+        // Still in flight: nothing new goes out
+        assert!(!panel.poll_flush().unwrap());
+        assert_eq!(dma.writes().len(), 2);
+
+        let mut polls = 2;
+        while !panel.poll_flush().unwrap() {
+            polls += 1;
+        }
+        assert_eq!(polls, 3 * HEIGHT);
+        assert_eq!(dma.writes().len(), 2 * HEIGHT);
+        assert!(!panel.is_flushing());
+    }
+
+    #[test]
+    fn drawing_during_a_dma_flush_goes_to_the_back_buffer() {
+        let dma = MockDmaTransport::new(2);
+        let mut panel = Sh1122::new(dma.clone());
+
+        draw_volume(&mut panel, 10);
+        panel.start_flush().unwrap();
+        for _ in 0..HEIGHT {
+            panel.poll_flush().unwrap();
+        }
+
+        // The next frame is composed while the first is halfway out
+        draw_volume(&mut panel, 99);
+        while !panel.poll_flush().unwrap() {}
+
+        let as_i2c = |writes: &[(bool, Vec<u8>)]| -> Vec<(u8, Vec<u8>)> {
+            writes.iter()
+                .map(|(data, bytes)| (0x3C, [&[if *data { 0x40 } else { 0x00 }], bytes.as_slice()].concat()))
+                .collect()
+        };
+
+        let mut ram = [0u8; BUFFER_SIZE];
+        emulate(&as_i2c(&dma.writes()), &mut ram);
+        let mut expected = FrameBuffer::new();
+        Text::new("10%", Point::new(220, 13), MonoTextStyle::new(&FONT_7X13_BOLD, Gray4::new(15)))
+            .draw(&mut expected).unwrap();
+        assert_eq!(&ram, expected.as_bytes());
+
+        let before = dma.writes().len();
+        panel.flush().unwrap();
+        assert!(dma.writes().len() - before < HEIGHT);
+
+        emulate(&as_i2c(&dma.writes()), &mut ram);
+        assert_eq!(&ram, panel.buffer.as_bytes());
+    }
+}
@@ -0,0 +1,321 @@
+// Golden image checks for screen layouts. Frames are stored in `snapshots/<name>.txt` as one
+// line per row and one hex digit per pixel, with `.` for black, so a changed golden shows
+// up in a git diff as the picture itself.
+//
+// Regenerate goldens after an intended layout change with:
+//
+//   UPDATE_SNAPSHOTS=1 cargo test-host snapshot
+//
+// On a mismatch the frame that was drawn is written next to the golden as `<name>.actual.txt`.
+
+use std::fmt::Write as _;
+use std::path::PathBuf;
+use std::string::String;
+use std::vec::Vec;
+use crate::framebuffer::{FrameBuffer, HEIGHT, WIDTH};
+
+// Rows and columns of context shown around the changed area
+const CONTEXT: usize = 2;
+const MAX_DIFF_ROWS: usize = 24;
+
+pub fn assert_snapshot(name: &str, frame: &FrameBuffer) {
+    let actual = encode(frame);
+    let golden = path(name, "txt");
+    let actual_path = path(name, "actual.txt");
+
+    if std::env::var_os("UPDATE_SNAPSHOTS").is_some() {
+        std::fs::create_dir_all(golden.parent().unwrap()).unwrap();
+        std::fs::write(&golden, &actual).unwrap();
+        std::fs::remove_file(&actual_path).ok();
+        return;
+    }
+
+    let Ok(expected) = std::fs::read_to_string(&golden) else {
+        std::fs::write(&actual_path, &actual).ok();
+        panic!(
+            "no golden image for `{}` at {}; run with UPDATE_SNAPSHOTS=1 to create it",
+            name,
+            golden.display()
+        );
+    };
+
+    if let Some(diff) = diff(&expected, &actual) {
+        std::fs::write(&actual_path, &actual).ok();
+        panic!(
+            "`{}` differs from its golden image\n{}\nwrote {}; run with UPDATE_SNAPSHOTS=1 if the change is intended",
+            name,
+            diff,
+            actual_path.display()
+        );
+    }
+
+    std::fs::remove_file(&actual_path).ok();
+}
+
+fn path(name: &str, extension: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("snapshots")
+        .join(std::format!("{}.{}", name, extension))
+}
+
+pub fn encode(frame: &FrameBuffer) -> String {
+    let mut out = String::with_capacity((WIDTH + 1) * HEIGHT);
+    for y in 0..HEIGHT {
+        for x in 0..WIDTH {
+            out.push(match frame.pixel(x, y) {
+                0 => '.',
+                level => char::from_digit(level as u32, 16).unwrap(),
+            });
+        }
+        out.push('\n');
+    }
+    out
+}
+
+// Describes where two encoded frames differ: how many pixels, the bounding box, and the
+// expected and actual rows cropped to it. `None` if they match.
+pub fn diff(expected: &str, actual: &str) -> Option<String> {
+    let expected: Vec<&[u8]> = expected.lines().map(str::as_bytes).collect();
+    let actual: Vec<&[u8]> = actual.lines().map(str::as_bytes).collect();
+
+    if expected.len() != actual.len() || expected.iter().zip(&actual).any(|(e, a)| e.len() != a.len()) {
+        return Some(std::format!(
+            "golden is {} rows of {} pixels, frame is {} rows of {}",
+            expected.len(),
+            expected.first().map_or(0, |row| row.len()),
+            actual.len(),
+            actual.first().map_or(0, |row| row.len())
+        ));
+    }
+
+    let changed: Vec<(usize, usize)> = expected.iter().zip(&actual).enumerate()
+        .flat_map(|(y, (e, a))| {
+            e.iter().zip(a.iter()).enumerate().filter(|(_, (e, a))| e != a).map(move |(x, _)| (x, y))
+        })
+        .collect();
+
+    if changed.is_empty() {
+        return None;
+    }
+
+    let (min_x, max_x) = range(changed.iter().map(|(x, _)| *x));
+    let (min_y, max_y) = range(changed.iter().map(|(_, y)| *y));
+    let mut out = std::format!(
+        "{} pixel{} changed in x {}..={}, y {}..={} (^ marks a change)\n",
+        changed.len(), if changed.len() == 1 { "" } else { "s" }, min_x, max_x, min_y, max_y
+    );
+
+    let (left, right) = (min_x.saturating_sub(CONTEXT), (max_x + CONTEXT).min(expected[0].len() - 1));
+    let (top, bottom) = (min_y.saturating_sub(CONTEXT), (max_y + CONTEXT).min(expected.len() - 1));
+
+    for y in (top..=bottom).take(MAX_DIFF_ROWS) {
+        let e = &expected[y][left..=right];
+        let a = &actual[y][left..=right];
+        let marks: String = e.iter().zip(a).map(|(e, a)| if e == a { ' ' } else { '^' }).collect();
+
+        writeln!(out, "  {:2} expected {}", y, std::str::from_utf8(e).unwrap()).unwrap();
+        writeln!(out, "       actual {}", std::str::from_utf8(a).unwrap()).unwrap();
+        if marks.contains('^') {
+            writeln!(out, "              {}", marks.trim_end()).unwrap();
+        }
+    }
+
+    if bottom + 1 - top > MAX_DIFF_ROWS {
+        writeln!(out, "  ... {} more rows", bottom + 1 - top - MAX_DIFF_ROWS).unwrap();
+    }
+
+    Some(out)
+}
+
+fn range(values: impl Iterator<Item = usize> + Clone) -> (usize, usize) {
+    (values.clone().min().unwrap(), values.max().unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::ToString;
+    use crate::clock::{Clock, Duration, Instant, MockClock};
+    use crate::home::HomeScreen;
+    use crate::menu::{MenuItem, MenuScreen};
+    use crate::screen::{InputAction, InputEvent, PRIMARY_ENCODER, Screen};
+    use crate::settings::{power_monitor_menu, settings_menu};
+    use crate::source::source_menu;
+    use crate::state::{AudioSource, PowerSetting, State};
+
+    // Runs a screen's updates up to `at`, like the display loop would, then draws it.
+    fn render<S: Screen>(screen: &mut S, state: &State, at: Instant) -> FrameBuffer {
+        let clock = MockClock::new();
+        let prev = state.clone();
+        while clock.now() < at {
+            screen.update(&prev, state, clock.now());
+            clock.advance(Duration::from_millis(5));
+        }
+        screen.update(&prev, state, clock.now());
+
+        let mut frame = FrameBuffer::new();
+        screen.draw(state, &mut frame);
+        frame
+    }
+
+    fn usb() -> State {
+        let state = State::new();
+        state.set_audio_source(AudioSource::USB);
+        state
+    }
+
+    fn playing() -> State {
+        let state = usb();
+        state.set_playback(Duration::from_secs(83), Duration::from_secs(227), true, Instant::default());
+        state
+    }
+
+    #[test]
+    fn encode_is_one_digit_per_pixel() {
+        let mut frame = FrameBuffer::new();
+        frame.set_pixel(0, 0, 15);
+        frame.set_pixel(1, 0, 7);
+        frame.set_pixel(255, 63, 1);
+
+        let text = encode(&frame);
+        let rows: Vec<&str> = text.lines().collect();
+        assert_eq!(rows.len(), HEIGHT);
+        assert!(rows.iter().all(|row| row.len() == WIDTH));
+        assert!(rows[0].starts_with("f7."));
+        assert!(rows[63].ends_with(".1"));
+    }
+
+    #[test]
+    fn diff_crops_to_the_change() {
+        let mut frame = FrameBuffer::new();
+        let expected = encode(&frame);
+        assert_eq!(diff(&expected, &expected), None);
+
+        frame.set_pixel(100, 20, 15);
+        frame.set_pixel(103, 21, 4);
+        let report = diff(&expected, &encode(&frame)).unwrap();
+
+        assert!(report.starts_with("2 pixels changed in x 100..=103, y 20..=21"));
+        assert!(report.contains("  20 expected ........\n       actual ..f.....\n                ^\n"));
+        assert!(report.contains("  21 expected ........\n       actual .....4..\n                   ^\n"));
+        // Two rows of context either side, and a marker line under each changed row
+        assert_eq!(report.lines().count(), 1 + 6 * 2 + 2);
+    }
+
+    #[test]
+    fn diff_reports_size_mismatch() {
+        let report = diff("..\n..\n", "...\n...\n").unwrap();
+        assert_eq!(report, "golden is 2 rows of 2 pixels, frame is 2 rows of 3");
+    }
+
+    #[test]
+    fn snapshot_home_paused() {
+        let mut screen = HomeScreen::new();
+        assert_snapshot("home_paused", &render(&mut screen, &usb(), Instant::default()));
+    }
+
+    #[test]
+    fn snapshot_home_playing() {
+        let state = playing();
+        state.set_power_setting(PowerSetting::ON);
+        state.set_antenna_up(true);
+        state.set_voltage(14.4);
+        state.set_current(11.5);
+        let mut screen = HomeScreen::new();
+        assert_snapshot("home_playing", &render(&mut screen, &state, Instant::from_millis(1_000)));
+    }
+
+    #[test]
+    fn snapshot_home_unrounded_reading() {
+        // Straight from the INA219 without rounding, as a raw 3299 converts
+        let state = usb();
+        state.set_voltage(13.196001);
+        state.set_current(4.2370005);
+        let mut screen = HomeScreen::new();
+        assert_snapshot("home_unrounded_reading", &render(&mut screen, &state, Instant::default()));
+    }
+
+    #[test]
+    fn snapshot_home_title_scrolled() {
+        let state = playing();
+        let mut screen = HomeScreen::new();
+        assert_snapshot("home_title_scrolled", &render(&mut screen, &state, Instant::from_millis(3_000)));
+    }
+
+    #[test]
+    fn snapshot_home_no_track_length() {
+        let state = usb();
+        state.set_track_title("Live stream");
+        state.set_track_artist("Radio 2");
+        state.set_power_setting(PowerSetting::OFF);
+        state.set_playback(Duration::ZERO, Duration::ZERO, true, Instant::default());
+        let mut screen = HomeScreen::new();
+        assert_snapshot("home_no_track_length", &render(&mut screen, &state, Instant::default()));
+    }
+
+    #[test]
+    fn snapshot_home_tuner() {
+        let state = State::new();
+        state.set_tuner(92_600, "Radio 2");
+        state.set_antenna_up(true);
+        let mut screen = HomeScreen::new();
+        assert_snapshot("home_tuner", &render(&mut screen, &state, Instant::default()));
+    }
+
+    #[test]
+    fn snapshot_home_bluetooth_disconnected() {
+        let state = State::new();
+        state.set_audio_source(AudioSource::BLUETOOTH);
+        let mut screen = HomeScreen::new();
+        assert_snapshot("home_bluetooth_disconnected", &render(&mut screen, &state, Instant::default()));
+    }
+
+    #[test]
+    fn snapshot_home_volume_overlay() {
+        let state = State::new();
+        let mut screen = HomeScreen::new();
+        screen.handle_event(&state, InputEvent::new(PRIMARY_ENCODER, InputAction::Turn(6)));
+
+        // The overlay shows for the volume change seen on the first update
+        let prev = State::new();
+        screen.update(&prev, &state, Instant::default());
+        let mut frame = FrameBuffer::new();
+        screen.draw(&state, &mut frame);
+        assert_snapshot("home_volume_overlay", &frame);
+    }
+
+    #[test]
+    fn snapshot_settings_menu() {
+        let state = State::new();
+        let mut menu = settings_menu();
+        menu.handle_event(&state, InputEvent::new(PRIMARY_ENCODER, InputAction::Turn(1)));
+        assert_snapshot("settings_menu", &render(&mut menu, &state, Instant::default()));
+    }
+
+    #[test]
+    fn snapshot_source_menu() {
+        let state = State::new();
+        state.set_audio_source(AudioSource::AUX);
+        let mut menu = source_menu(&state);
+        assert_snapshot("source_menu", &render(&mut menu, &state, Instant::default()));
+    }
+
+    #[test]
+    fn snapshot_power_monitor_menu() {
+        let state = State::new();
+        state.set_voltage(14.4);
+        state.set_current(11.5);
+        let mut menu = power_monitor_menu();
+        assert_snapshot("power_monitor_menu", &render(&mut menu, &state, Instant::default()));
+    }
+
+    #[test]
+    fn snapshot_menu_scrolled() {
+        let state = State::new();
+        let mut menu = (1..=8).fold(MenuScreen::new("LONG MENU"), |menu, i| {
+            menu.with_item(MenuItem::new(&std::format!("Item {}", i)).with_value(|_| "value".to_string()))
+        });
+        menu.handle_event(&state, InputEvent::new(PRIMARY_ENCODER, InputAction::Turn(5)));
+        assert_snapshot("menu_scrolled", &render(&mut menu, &state, Instant::default()));
+    }
+}
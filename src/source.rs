@@ -0,0 +1,143 @@
+use alloc::format;
+use alloc::string::{String, ToString};
+use crate::menu::{MenuItem, MenuScreen};
+use crate::screen::Navigation;
+use crate::state::{AudioSource, State};
+
+// Frequencies from here up are FM, shown in MHz; below are AM, shown in kHz
+const FM_MIN_KHZ: u32 = 30_000;
+
+// Picks the audio source, opened from the home screen and the settings. The cursor starts
+// on the current source, and picking one switches to it and goes back.
+pub fn source_menu(state: &State) -> MenuScreen {
+    let current = AudioSource::ALL.iter().position(|source| *source == state.audio_source());
+
+    MenuScreen::new("SOURCE")
+        .with_item(MenuItem::new("Tuner")
+            .with_value(|state| status(state, AudioSource::TUNER))
+            .on_select(|state| select(state, AudioSource::TUNER)))
+        .with_item(MenuItem::new("Bluetooth")
+            .with_value(|state| status(state, AudioSource::BLUETOOTH))
+            .on_select(|state| select(state, AudioSource::BLUETOOTH)))
+        .with_item(MenuItem::new("AUX")
+            .with_value(|state| status(state, AudioSource::AUX))
+            .on_select(|state| select(state, AudioSource::AUX)))
+        .with_item(MenuItem::new("USB")
+            .with_value(|state| status(state, AudioSource::USB))
+            .on_select(|state| select(state, AudioSource::USB)))
+        .with_cursor(current.unwrap_or(0))
+}
+
+fn status(state: &State, source: AudioSource) -> String {
+    if state.audio_source() == source {
+        "ACTIVE".to_string()
+    } else if source == AudioSource::BLUETOOTH && state.bluetooth_device().is_empty() {
+        "NO DEVICE".to_string()
+    } else {
+        String::new()
+    }
+}
+
+fn select(state: &State, source: AudioSource) -> Navigation {
+    state.select_audio_source(source);
+    Navigation::Pop
+}
+
+// The two lines the home screen shows for the current source.
+pub fn now_playing(state: &State) -> (String, String) {
+    match state.audio_source() {
+        AudioSource::TUNER => {
+            let station = state.tuner_station();
+            let frequency = format_frequency(state.tuner_frequency());
+            match (station.is_empty(), frequency.is_empty()) {
+                (false, _) => (station, frequency),
+                (true, false) => (frequency, String::new()),
+                (true, true) => ("No station".to_string(), String::new()),
+            }
+        }
+        AudioSource::BLUETOOTH if state.bluetooth_device().is_empty() => {
+            ("No device connected".to_string(), String::new())
+        }
+        AudioSource::AUX => ("AUX input".to_string(), String::new()),
+        AudioSource::BLUETOOTH | AudioSource::USB => (state.track_title(), state.track_artist()),
+    }
+}
+
+// "FM 92.6 MHz" or "AM 1008 kHz", empty if the frequency isn't known.
+pub fn format_frequency(khz: u32) -> String {
+    match khz {
+        0 => String::new(),
+        khz if khz >= FM_MIN_KHZ => format!("FM {}.{} MHz", khz / 1000, khz % 1000 / 100),
+        khz => format!("AM {} kHz", khz),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::screen::{InputAction, InputEvent, PRIMARY_ENCODER, Screen};
+    use crate::settings::settings_menu;
+
+    fn press() -> InputEvent {
+        InputEvent::new(PRIMARY_ENCODER, InputAction::ShortPress)
+    }
+
+    #[test]
+    fn opens_on_the_current_source() {
+        let state = State::new();
+        state.set_audio_source(AudioSource::AUX);
+        let menu = source_menu(&state);
+        assert_eq!(AudioSource::ALL[menu.cursor()], AudioSource::AUX);
+    }
+
+    #[test]
+    fn picking_a_source_switches_and_goes_back() {
+        let state = State::new();
+        let mut menu = source_menu(&state);
+
+        menu.handle_event(&state, InputEvent::new(PRIMARY_ENCODER, InputAction::Turn(3)));
+        let navigation = menu.handle_event(&state, press());
+
+        assert!(matches!(navigation, Navigation::Pop));
+        assert_eq!(state.audio_source(), AudioSource::USB);
+        assert_eq!(state.take_source_request(), Some(AudioSource::USB));
+    }
+
+    #[test]
+    fn reachable_from_settings() {
+        let state = State::new();
+        let mut menu = settings_menu();
+
+        menu.handle_event(&state, InputEvent::new(PRIMARY_ENCODER, InputAction::Turn(1)));
+        assert!(state.navigate(menu.handle_event(&state, press())));
+        assert_eq!(state.screen_depth(), 2);
+        assert_eq!(state.audio_source(), AudioSource::TUNER);
+    }
+
+    #[test]
+    fn lines_follow_the_source() {
+        let state = State::new();
+        assert_eq!(now_playing(&state), ("No station".to_string(), String::new()));
+
+        state.set_tuner(92_600, "");
+        assert_eq!(now_playing(&state), ("FM 92.6 MHz".to_string(), String::new()));
+        state.set_tuner(92_600, "Radio 2");
+        assert_eq!(now_playing(&state), ("Radio 2".to_string(), "FM 92.6 MHz".to_string()));
+
+        state.set_audio_source(AudioSource::BLUETOOTH);
+        assert_eq!(now_playing(&state).0, "No device connected");
+        state.set_bluetooth_device("Pixel 7");
+        assert_eq!(now_playing(&state), (state.track_title(), state.track_artist()));
+
+        state.set_audio_source(AudioSource::AUX);
+        assert_eq!(now_playing(&state).0, "AUX input");
+    }
+
+    #[test]
+    fn frequencies_use_the_band_units() {
+        assert_eq!(format_frequency(0), "");
+        assert_eq!(format_frequency(87_500), "FM 87.5 MHz");
+        assert_eq!(format_frequency(101_200), "FM 101.2 MHz");
+        assert_eq!(format_frequency(1_008), "AM 1008 kHz");
+    }
+}
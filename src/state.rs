@@ -0,0 +1,455 @@
+use alloc::string::{String, ToString};
+use core::cell::{Cell, RefCell};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cell::RefMut;
+use crate::clock::{Duration, Instant};
+use crate::home::HomeScreen;
+use crate::menu::MenuScreen;
+use crate::screen::Navigation;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum PowerSetting {
+    ON,
+    AUTO,
+    OFF
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TrackSkip {
+    Next,
+    Previous,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum AudioSource {
+    TUNER,
+    BLUETOOTH,
+    AUX,
+    USB
+}
+
+impl AudioSource {
+    // Also the order of the source menu
+    pub const ALL: [AudioSource; 4] = [
+        AudioSource::TUNER,
+        AudioSource::BLUETOOTH,
+        AudioSource::AUX,
+        AudioSource::USB,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            AudioSource::TUNER => "TUNER",
+            AudioSource::BLUETOOTH => "BLUETOOTH",
+            AudioSource::AUX => "AUX",
+            AudioSource::USB => "USB",
+        }
+    }
+
+    // Whether the source plays tracks with a title, artist and position
+    pub fn has_tracks(&self) -> bool {
+        matches!(self, AudioSource::BLUETOOTH | AudioSource::USB)
+    }
+}
+
+#[derive(Clone)]
+pub enum ActiveScreen {
+    Home(HomeScreen),
+    Menu(MenuScreen),
+}
+
+
+#[derive(Clone)]
+pub struct State {
+    accessory_power: Cell<bool>,
+    power_setting: Cell<PowerSetting>,
+    antenna_up: Cell<bool>,
+    audio_source: Cell<AudioSource>,
+    // Source picked on the device that the host hasn't been told about yet
+    source_request: Cell<Option<AudioSource>>,
+    // Track skips asked for on the device and not sent to the host yet, positive forward
+    track_skips: Cell<i32>,
+    // Tuned frequency in kHz, zero if unknown, and the station name if it broadcasts one
+    tuner_frequency: Cell<u32>,
+    tuner_station: RefCell<String>,
+    // Name of the connected phone, empty when nothing is connected
+    bluetooth_device: RefCell<String>,
+    voltage: Cell<f32>,
+    current: Cell<f32>,
+    track_title: RefCell<String>,
+    track_artist: RefCell<String>,
+    // Position as last reported by the host, and when
+    track_position: Cell<Duration>,
+    track_position_at: Cell<Instant>,
+    // Zero when the source has no track length, like the tuner
+    track_duration: Cell<Duration>,
+    playing: Cell<bool>,
+    volume: Cell<u32>,
+    // Navigation stack, never empty; the last screen is the one showing
+    screens: RefCell<Vec<ActiveScreen>>,
+}
+
+impl State {
+    pub fn new() -> Self {
+        State {
+            accessory_power: Cell::new(true),
+            power_setting: Cell::new(PowerSetting::AUTO),
+            antenna_up: Cell::new(false),
+            audio_source: Cell::new(AudioSource::TUNER),
+            source_request: Cell::new(None),
+            track_skips: Cell::new(0),
+            tuner_frequency: Cell::new(0),
+            tuner_station: RefCell::new(String::new()),
+            bluetooth_device: RefCell::new(String::new()),
+            voltage: Cell::new(13.2),
+            current: Cell::new(2.6),
+            track_title: RefCell::new("Plastic Beach (feat. Mick Jones and Paul Simonon)".to_string()),
+            track_artist: RefCell::new("Gorillaz".to_string()),
+            track_position: Cell::new(Duration::ZERO),
+            track_position_at: Cell::new(Instant::default()),
+            track_duration: Cell::new(Duration::from_secs(227)),
+            playing: Cell::new(false),
+            volume: Cell::new(50),
+            screens: RefCell::new(vec![ActiveScreen::Home(HomeScreen::new())]),
+        }
+    }
+
+    pub fn accessory_power(&self) -> bool {
+        self.accessory_power.get()
+    }
+
+    pub fn set_accessory_power(&self, value: bool) {
+        self.accessory_power.set(value);
+    }
+
+    pub fn power_setting(&self) -> PowerSetting {
+        self.power_setting.get()
+    }
+
+    pub fn set_power_setting(&self, value: PowerSetting) {
+        self.power_setting.set(value);
+    }
+
+    pub fn antenna_up(&self) -> bool {
+        self.antenna_up.get()
+    }
+
+    pub fn set_antenna_up(&self, value: bool) {
+        self.antenna_up.set(value);
+    }
+
+    pub fn audio_source(&self) -> AudioSource {
+        self.audio_source.get()
+    }
+
+    pub fn set_audio_source(&self, value: AudioSource) {
+        self.audio_source.set(value);
+    }
+
+    // The only part of the source the antenna cares about
+    pub fn tuner_active(&self) -> bool {
+        self.audio_source.get() == AudioSource::TUNER
+    }
+
+    // Switches source on the user's behalf and asks the host to follow.
+    pub fn select_audio_source(&self, value: AudioSource) {
+        self.audio_source.set(value);
+        self.source_request.set(Some(value));
+    }
+
+    pub fn take_source_request(&self) -> Option<AudioSource> {
+        self.source_request.take()
+    }
+
+    // Asks the host to skip a track. A skip back cancels a forward one not sent yet.
+    pub fn skip_track(&self, skip: TrackSkip) {
+        let step = match skip {
+            TrackSkip::Next => 1,
+            TrackSkip::Previous => -1,
+        };
+        self.track_skips.set(self.track_skips.get() + step);
+    }
+
+    // Takes one pending skip, so a key pressed twice skips twice.
+    pub fn take_track_skip(&self) -> Option<TrackSkip> {
+        let skips = self.track_skips.get();
+        self.track_skips.set(skips - skips.signum());
+        match skips.signum() {
+            1 => Some(TrackSkip::Next),
+            -1 => Some(TrackSkip::Previous),
+            _ => None,
+        }
+    }
+
+    pub fn tuner_frequency(&self) -> u32 {
+        self.tuner_frequency.get()
+    }
+
+    pub fn tuner_station(&self) -> String {
+        self.tuner_station.borrow().clone()
+    }
+
+    pub fn set_tuner(&self, frequency_khz: u32, station: &str) {
+        self.tuner_frequency.set(frequency_khz);
+        *self.tuner_station.borrow_mut() = String::from(station);
+    }
+
+    pub fn bluetooth_device(&self) -> String {
+        self.bluetooth_device.borrow().clone()
+    }
+
+    pub fn set_bluetooth_device(&self, value: &str) {
+        *self.bluetooth_device.borrow_mut() = String::from(value);
+    }
+
+    pub fn voltage(&self) -> f32 {
+        self.voltage.get()
+    }
+
+    pub fn set_voltage(&self, value: f32) {
+        self.voltage.set(value);
+    }
+
+    pub fn current(&self) -> f32 {
+        self.current.get()
+    }
+
+    pub fn set_current(&self, value: f32) {
+        self.current.set(value);
+    }
+
+    pub fn track_title(&self) -> String {
+        self.track_title.borrow().clone()
+    }
+
+    pub fn set_track_title(&self, value: &str) {
+        *self.track_title.borrow_mut() = String::from(value);
+    }
+
+    pub fn track_artist(&self) -> String {
+        self.track_artist.borrow().clone()
+    }
+
+    pub fn set_track_artist(&self, value: &str) {
+        *self.track_artist.borrow_mut() = String::from(value);
+    }
+
+    // Where playback is at `now`, counting on from the host's last report while playing.
+    pub fn track_position(&self, now: Instant) -> Duration {
+        let mut position = self.track_position.get();
+        if self.playing.get() {
+            position += now.duration_since(self.track_position_at.get());
+        }
+
+        match self.track_duration.get() {
+            Duration::ZERO => position,
+            duration => position.min(duration),
+        }
+    }
+
+    pub fn track_duration(&self) -> Duration {
+        self.track_duration.get()
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.playing.get()
+    }
+
+    // Records a playback report from the host, received at `now`.
+    pub fn set_playback(&self, position: Duration, duration: Duration, playing: bool, now: Instant) {
+        self.track_position.set(position);
+        self.track_position_at.set(now);
+        self.track_duration.set(duration);
+        self.playing.set(playing);
+    }
+
+    pub fn volume(&self) -> u32 {
+        self.volume.get()
+    }
+
+    pub fn set_volume(&self, value: u32) {
+        self.volume.set(value);
+    }
+
+    pub fn current_screen(&self) -> RefMut<'_, ActiveScreen> {
+        RefMut::map(self.screens.borrow_mut(), |screens| screens.last_mut().unwrap())
+    }
+
+    // Drops the whole navigation stack in favour of a single screen.
+    pub fn set_current_screen(&self, screen: ActiveScreen) {
+        *self.screens.borrow_mut() = vec![screen];
+    }
+
+    pub fn push_screen(&self, screen: ActiveScreen) {
+        self.screens.borrow_mut().push(screen);
+    }
+
+    // Returns false at the bottom of the stack, which always keeps one screen.
+    pub fn pop_screen(&self) -> bool {
+        let mut screens = self.screens.borrow_mut();
+        if screens.len() > 1 {
+            screens.pop();
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn replace_screen(&self, screen: ActiveScreen) {
+        *self.current_screen() = screen;
+    }
+
+    pub fn screen_depth(&self) -> usize {
+        self.screens.borrow().len()
+    }
+
+    // Applies a screen's request. Returns true if a different screen is now showing.
+    pub fn navigate(&self, navigation: Navigation) -> bool {
+        match navigation {
+            Navigation::Stay => false,
+            Navigation::Push(screen) => {
+                self.push_screen(screen);
+                true
+            }
+            Navigation::Pop => self.pop_screen(),
+            Navigation::Replace(screen) => {
+                self.replace_screen(screen);
+                true
+            }
+        }
+    }
+
+    pub fn matches(&self, other: &State) -> bool {
+        self.accessory_power.get() == other.accessory_power.get() &&
+        self.power_setting.get() == other.power_setting.get() &&
+        self.antenna_up.get() == other.antenna_up.get() &&
+        self.audio_source.get() == other.audio_source.get() &&
+        self.tuner_frequency.get() == other.tuner_frequency.get() &&
+        self.tuner_station.borrow().as_str() == other.tuner_station.borrow().as_str() &&
+        self.bluetooth_device.borrow().as_str() == other.bluetooth_device.borrow().as_str() &&
+        self.voltage.get() == other.voltage.get() &&
+        self.current.get() == other.current.get() &&
+        self.track_title.borrow().as_str() == other.track_title.borrow().as_str() &&
+        self.track_artist.borrow().as_str() == other.track_artist.borrow().as_str() &&
+        self.track_position.get() == other.track_position.get() &&
+        self.track_position_at.get() == other.track_position_at.get() &&
+        self.track_duration.get() == other.track_duration.get() &&
+        self.playing.get() == other.playing.get() &&
+        self.screen_depth() == other.screen_depth() &&
+        self.screens.borrow().last().map(core::mem::discriminant) == other.screens.borrow().last().map(core::mem::discriminant)
+    }
+}
+
+impl Default for State {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PartialEq for State {
+    fn eq(&self, other: &Self) -> bool {
+        self.matches(other)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clones_compare_equal_until_changed() {
+        let state = State::new();
+        let snapshot = state.clone();
+        assert!(state == snapshot);
+
+        state.set_track_title("Stylo");
+        assert!(state != snapshot);
+    }
+
+    #[test]
+    fn clone_does_not_share_cells() {
+        let state = State::new();
+        let snapshot = state.clone();
+
+        state.set_voltage(12.1);
+        assert_eq!(snapshot.voltage(), 13.2);
+    }
+
+    #[test]
+    fn position_runs_on_while_playing() {
+        let state = State::new();
+        let at = Instant::from_millis(10_000);
+        state.set_playback(Duration::from_secs(30), Duration::from_secs(200), true, at);
+
+        assert_eq!(state.track_position(at), Duration::from_secs(30));
+        assert_eq!(state.track_position(at + Duration::from_millis(2500)), Duration::from_millis(32_500));
+        // Never past the end of the track
+        assert_eq!(state.track_position(at + Duration::from_secs(600)), Duration::from_secs(200));
+
+        state.set_playback(Duration::from_secs(45), Duration::from_secs(200), false, at);
+        assert_eq!(state.track_position(at + Duration::from_secs(60)), Duration::from_secs(45));
+    }
+
+    #[test]
+    fn position_is_unbounded_without_a_duration() {
+        let state = State::new();
+        state.set_playback(Duration::ZERO, Duration::ZERO, true, Instant::default());
+        assert_eq!(state.track_position(Instant::from_millis(3_600_000)), Duration::from_secs(3600));
+    }
+
+    #[test]
+    fn only_sources_picked_on_the_device_are_requested() {
+        let state = State::new();
+        state.set_audio_source(AudioSource::BLUETOOTH);
+        assert_eq!(state.take_source_request(), None);
+
+        state.select_audio_source(AudioSource::AUX);
+        assert_eq!(state.audio_source(), AudioSource::AUX);
+        assert_eq!(state.take_source_request(), Some(AudioSource::AUX));
+        assert_eq!(state.take_source_request(), None);
+    }
+
+    #[test]
+    fn track_skips_are_taken_one_at_a_time() {
+        let state = State::new();
+        state.skip_track(TrackSkip::Next);
+        state.skip_track(TrackSkip::Next);
+        state.skip_track(TrackSkip::Next);
+        state.skip_track(TrackSkip::Previous);
+
+        assert_eq!(state.take_track_skip(), Some(TrackSkip::Next));
+        assert_eq!(state.take_track_skip(), Some(TrackSkip::Next));
+        assert_eq!(state.take_track_skip(), None);
+
+        state.skip_track(TrackSkip::Previous);
+        assert_eq!(state.take_track_skip(), Some(TrackSkip::Previous));
+        assert_eq!(state.take_track_skip(), None);
+    }
+
+    #[test]
+    fn navigation_stack_keeps_its_root() {
+        let state = State::new();
+        let menu = || ActiveScreen::Menu(MenuScreen::new("Settings"));
+
+        assert!(!state.navigate(Navigation::Pop));
+        assert!(!state.navigate(Navigation::Stay));
+        assert_eq!(state.screen_depth(), 1);
+
+        let snapshot = state.clone();
+        assert!(state.navigate(Navigation::Push(menu())));
+        assert!(state.navigate(Navigation::Push(menu())));
+        assert_eq!(state.screen_depth(), 3);
+        assert!(state != snapshot);
+
+        assert!(state.navigate(Navigation::Replace(ActiveScreen::Home(HomeScreen::new()))));
+        assert_eq!(state.screen_depth(), 3);
+        assert!(matches!(*state.current_screen(), ActiveScreen::Home(_)));
+
+        assert!(state.navigate(Navigation::Pop));
+        assert!(matches!(*state.current_screen(), ActiveScreen::Menu(_)));
+        assert!(state.navigate(Navigation::Pop));
+        assert!(!state.navigate(Navigation::Pop));
+        assert!(state == snapshot);
+    }
+}
@@ -0,0 +1,175 @@
+// Command/data transports for the SH1122. The controller takes the same byte stream over
+// I2C and SPI; only the way commands are told apart from pixel data differs.
+
+use embedded_hal::digital::OutputPin;
+use embedded_hal::i2c::{self, I2c, NoAcknowledgeSource};
+use embedded_hal::spi::{self, SpiDevice};
+
+const I2C_COMMAND: u8 = 0x00;
+const I2C_DATA: u8 = 0x40;
+const I2C_CHUNK: usize = 128;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Sh1122Error {
+    // Nothing acknowledged the address: panel missing, unpowered or wired wrong
+    AddressNack,
+    // Any other I2C failure (arbitration, data NACK, timeout, ...)
+    Bus(i2c::ErrorKind),
+    Spi(spi::ErrorKind),
+    // The D/C line could not be driven
+    Pin,
+}
+
+impl Sh1122Error {
+    fn from_i2c<E: i2c::Error>(e: E) -> Self {
+        match e.kind() {
+            i2c::ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address) => Sh1122Error::AddressNack,
+            kind => Sh1122Error::Bus(kind),
+        }
+    }
+
+    fn from_spi<E: spi::Error>(e: E) -> Self {
+        Sh1122Error::Spi(e.kind())
+    }
+}
+
+// `command` and `data` block, waiting for any `start_data` transfer still in flight first.
+pub trait Transport {
+    fn command(&mut self, cmd: &[u8]) -> Result<(), Sh1122Error>;
+
+    fn data(&mut self, data: &[u8]) -> Result<(), Sh1122Error>;
+
+    // Starts sending pixel data without waiting for it to go out. Transports without DMA
+    // just send it, so they are never busy.
+    fn start_data(&mut self, data: &[u8]) -> Result<(), Sh1122Error> {
+        self.data(data)
+    }
+
+    // Whether the last `start_data` transfer has finished; a failed transfer is reported here.
+    fn poll(&mut self) -> Result<bool, Sh1122Error> {
+        Ok(true)
+    }
+}
+
+// I2C: every transfer starts with a control byte selecting command or data mode.
+pub struct I2cTransport<I>
+where
+    I: I2c
+{
+    i2c: I,
+    addr: u8,
+}
+
+impl<I> I2cTransport<I>
+where
+    I: I2c
+{
+    pub fn new(i2c: I, addr: u8) -> Self {
+        I2cTransport { i2c, addr }
+    }
+
+    fn write(&mut self, control: u8, bytes: &[u8]) -> Result<(), Sh1122Error> {
+        let mut buf = [0u8; 1 + I2C_CHUNK];
+        buf[0] = control;
+
+        for chunk in bytes.chunks(I2C_CHUNK) {
+            buf[1..=chunk.len()].copy_from_slice(chunk);
+            self.i2c.write(self.addr, &buf[..=chunk.len()]).map_err(Sh1122Error::from_i2c)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<I> Transport for I2cTransport<I>
+where
+    I: I2c
+{
+    fn command(&mut self, cmd: &[u8]) -> Result<(), Sh1122Error> {
+        self.write(I2C_COMMAND, cmd)
+    }
+
+    fn data(&mut self, data: &[u8]) -> Result<(), Sh1122Error> {
+        self.write(I2C_DATA, data)
+    }
+}
+
+// 4-wire SPI: the D/C pin is low for commands and high for display data.
+pub struct SpiTransport<S, DC>
+where
+    S: SpiDevice,
+    DC: OutputPin
+{
+    spi: S,
+    dc: DC,
+}
+
+impl<S, DC> SpiTransport<S, DC>
+where
+    S: SpiDevice,
+    DC: OutputPin
+{
+    pub fn new(spi: S, dc: DC) -> Self {
+        SpiTransport { spi, dc }
+    }
+}
+
+impl<S, DC> Transport for SpiTransport<S, DC>
+where
+    S: SpiDevice,
+    DC: OutputPin
+{
+    fn command(&mut self, cmd: &[u8]) -> Result<(), Sh1122Error> {
+        self.dc.set_low().map_err(|_| Sh1122Error::Pin)?;
+        self.spi.write(cmd).map_err(Sh1122Error::from_spi)
+    }
+
+    fn data(&mut self, data: &[u8]) -> Result<(), Sh1122Error> {
+        self.dc.set_high().map_err(|_| Sh1122Error::Pin)?;
+        self.spi.write(data).map_err(Sh1122Error::from_spi)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{MockI2c, MockPin, MockSpi};
+
+    #[test]
+    fn i2c_prefixes_control_byte_and_chunks_long_data() {
+        let i2c = MockI2c::new();
+        let mut transport = I2cTransport::new(i2c.clone(), 0x3C);
+
+        transport.command(&[0xB0, 5]).unwrap();
+        transport.data(&[0xAA; 200]).unwrap();
+
+        let bus = i2c.bus();
+        assert_eq!(bus.writes[0], (0x3C, [0x00, 0xB0, 5].to_vec()));
+        assert_eq!(bus.writes[1].1.len(), 1 + 128);
+        assert_eq!(bus.writes[1].1[0], 0x40);
+        assert_eq!(bus.writes[2].1.len(), 1 + 72);
+        assert_eq!(bus.writes[2].1[0], 0x40);
+    }
+
+    #[test]
+    fn spi_drives_dc_line() {
+        let dc = MockPin::new(true);
+        let spi = MockSpi::new(dc.clone());
+        let mut transport = SpiTransport::new(spi.clone(), dc.clone());
+
+        transport.command(&[0xAF]).unwrap();
+        transport.data(&[1, 2, 3]).unwrap();
+
+        assert_eq!(spi.writes(), [(false, [0xAF].to_vec()), (true, [1, 2, 3].to_vec())]);
+    }
+
+    #[test]
+    fn spi_errors_are_reported() {
+        let dc = MockPin::new(false);
+        let spi = MockSpi::new(dc.clone());
+        spi.fail_next(spi::ErrorKind::Overrun);
+        let mut transport = SpiTransport::new(spi, dc);
+
+        assert_eq!(transport.data(&[0]), Err(Sh1122Error::Spi(spi::ErrorKind::Overrun)));
+    }
+}